@@ -1,5 +1,5 @@
 use bytes::{Bytes};
-use futures::{Future, Stream};
+use futures::{Future, Sink, Stream};
 use std::io;
 
 // until we have trait aliases, found this hack at https://github.com/rust-lang/rfcs/pull/1733
@@ -9,6 +9,10 @@ use std::io;
 pub trait ByteStream: Stream<Item = Bytes, Error = io::Error> {}
 impl<T: Stream<Item = Bytes, Error = io::Error>> ByteStream for T {}
 
+/// Alias for `Sink<Bytes>` with an error type of `io::Error`
+pub trait ByteSink: Sink<SinkItem = Bytes, SinkError = io::Error> {}
+impl<T: Sink<SinkItem = Bytes, SinkError = io::Error>> ByteSink for T {}
+
 /// Alias for `Stream<Stream<Bytes>>` with an error type of `io::Error`
 pub trait ByteStreamStream<S: ByteStream>: Stream<Item = S, Error = io::Error> {}
 impl<S: ByteStream, T: Stream<Item = S, Error = io::Error>> ByteStreamStream<S> for T {}
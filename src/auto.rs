@@ -0,0 +1,328 @@
+use aliases::{ByteStream};
+use bytes::{Buf, Bytes, BytesMut, IntoBuf};
+use futures::{Async, Poll, Stream};
+use snap;
+use std::cmp;
+use std::collections::VecDeque;
+use std::io;
+use std::mem;
+
+use hadoop::{HadoopSnappyUncompress};
+use raw::{BlockDelimiter, SnappyRawUncompress};
+use shared::{STREAM_IDENTIFIER};
+use uncompress::{SnappyUncompress};
+use xerial::{XerialSnappyUncompress, XERIAL_MAGIC};
+
+// enough to rule in or out every format with a header: the xerial header
+// is 16 bytes, and a hadoop block header plus a raw snappy preamble is up
+// to 13.
+const DETECT_SIZE: usize = 16;
+
+// in passthrough mode, a raw stream is only recognized by decompressing its
+// first block, so there's a limit to how much we'll buffer to find out.
+const MAX_SNIFF_BLOCK: usize = 1 << 20;
+
+/// The container formats `SnappyAutoUncompress` can recognize.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SnappyFormat {
+  /// The snappy framing format, as used by `SnappyUncompress`.
+  Framed,
+
+  /// xerial/snappy-java streams, as used by `XerialSnappyUncompress`.
+  Xerial,
+
+  /// hadoop's `SnappyCodec` format, as used by `HadoopSnappyUncompress`.
+  Hadoop,
+
+  /// Raw snappy blocks, as used by `SnappyRawUncompress`.
+  Raw,
+
+  /// Not compressed at all (only in passthrough mode).
+  Uncompressed,
+}
+
+// replays the buffers that were read during detection, then carries on
+// with the original stream, unless it already ended.
+struct Rewind<S> where S: ByteStream {
+  stream: S,
+  buffered: VecDeque<Bytes>,
+  eof: bool,
+}
+
+impl<S> Stream for Rewind<S> where S: ByteStream {
+  type Item = Bytes;
+  type Error = io::Error;
+
+  fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+    if let Some(data) = self.buffered.pop_front() {
+      return Ok(Async::Ready(Some(data)));
+    }
+    if self.eof {
+      return Ok(Async::Ready(None));
+    }
+    let rv = self.stream.poll();
+    if let Ok(Async::Ready(None)) = rv {
+      self.eof = true;
+    }
+    rv
+  }
+}
+
+impl<S> Rewind<S> where S: ByteStream {
+  fn new(stream: S) -> Rewind<S> {
+    Rewind { stream, buffered: VecDeque::new(), eof: false }
+  }
+}
+
+enum State<S> where S: ByteStream {
+  Detecting(Rewind<S>),
+  Framed(SnappyUncompress<Rewind<S>>),
+  Xerial(XerialSnappyUncompress<Rewind<S>>),
+  Hadoop(HadoopSnappyUncompress<Rewind<S>>),
+  Raw(SnappyRawUncompress<Rewind<S>>),
+  Passthrough(Rewind<S>),
+  // only while switching from one state to another
+  Empty,
+}
+
+/// Uncompress a stream in any of the snappy container formats this crate
+/// supports, by looking at the first few bytes to figure out which one it
+/// is. Framed, xerial, and hadoop streams all have recognizable headers; if
+/// none of those match, the stream is assumed to be raw snappy blocks.
+///
+/// In passthrough mode, data that doesn't look compressed at all is passed
+/// through untouched. Raw blocks have no header, so to tell them apart from
+/// uncompressed data, the first block (up to 1MB) is test-decompressed.
+pub struct SnappyAutoUncompress<S> where S: ByteStream {
+  state: State<S>,
+  format: Option<SnappyFormat>,
+  raw_delimiter: BlockDelimiter,
+  passthrough: bool,
+
+  // a contiguous copy of the start of the stream, as much as detection
+  // needs.
+  sniffed: BytesMut,
+}
+
+impl<S> SnappyAutoUncompress<S> where S: ByteStream {
+  pub fn new(stream: S) -> SnappyAutoUncompress<S> {
+    SnappyAutoUncompress {
+      state: State::Detecting(Rewind::new(stream)),
+      format: None,
+      raw_delimiter: BlockDelimiter::None,
+      passthrough: false,
+      sniffed: BytesMut::new(),
+    }
+  }
+
+  /// How raw snappy blocks are delimited, if the stream turns out to be
+  /// raw. The default is `BlockDelimiter::None` (one block).
+  pub fn raw_delimiter(mut self, delimiter: BlockDelimiter) -> SnappyAutoUncompress<S> {
+    self.raw_delimiter = delimiter;
+    self
+  }
+
+  /// Pass through data that isn't snappy-compressed, instead of failing.
+  pub fn passthrough(mut self, enabled: bool) -> SnappyAutoUncompress<S> {
+    self.passthrough = enabled;
+    self
+  }
+
+  /// The detected format, once enough of the stream has been seen.
+  pub fn format(&self) -> Option<SnappyFormat> {
+    self.format
+  }
+
+  // how much of the stream `detect` might need to see before it decides.
+  // in passthrough mode, that's a whole raw block and its length prefix,
+  // plus a byte to prove an undelimited block is too long.
+  fn sniff_limit(&self) -> usize {
+    if self.passthrough {
+      cmp::max(DETECT_SIZE, 5 + snap::max_compress_len(MAX_SNIFF_BLOCK) + 1)
+    } else {
+      DETECT_SIZE
+    }
+  }
+
+  // has the input ended while detecting?
+  fn eof(&self) -> bool {
+    match self.state {
+      State::Detecting(ref rewind) => rewind.eof,
+      _ => true,
+    }
+  }
+
+  // look at what we've read so far, and pick a format if we can.
+  fn detect(&self) -> Option<SnappyFormat> {
+    let data = &self.sniffed[..];
+    if starts_with(data, STREAM_IDENTIFIER) {
+      return Some(SnappyFormat::Framed);
+    }
+    if starts_with(data, XERIAL_MAGIC) {
+      return Some(SnappyFormat::Xerial);
+    }
+    if data.len() < DETECT_SIZE && !self.eof() {
+      // might still turn out to be a header.
+      return None;
+    }
+    if data.is_empty() {
+      // nothing to decode; any format will do.
+      return Some(SnappyFormat::Raw);
+    }
+    if looks_like_hadoop(data) {
+      return Some(SnappyFormat::Hadoop);
+    }
+    if !self.passthrough {
+      return Some(SnappyFormat::Raw);
+    }
+    self.sniff_raw()
+  }
+
+  // in passthrough mode, decide between raw and uncompressed by trying to
+  // decompress the first raw block.
+  fn sniff_raw(&self) -> Option<SnappyFormat> {
+    let data = &self.sniffed[..];
+    let needed = match self.raw_delimiter {
+      BlockDelimiter::U32BigEndian | BlockDelimiter::U32LittleEndian => 4,
+      _ => 1,
+    };
+    if data.len() < needed {
+      return Some(SnappyFormat::Uncompressed);
+    }
+
+    let (prefix, length) = match self.raw_delimiter {
+      BlockDelimiter::None => {
+        // the whole stream is one block, so it has to end soon.
+        match snap::decompress_len(data) {
+          Ok(n) if n <= MAX_SNIFF_BLOCK => {
+            if data.len() > snap::max_compress_len(n) {
+              return Some(SnappyFormat::Uncompressed);
+            }
+            if !self.eof() {
+              return None;
+            }
+            (0, data.len())
+          },
+          _ => return Some(SnappyFormat::Uncompressed),
+        }
+      },
+      BlockDelimiter::U32BigEndian => (4, (&data[..4]).into_buf().get_u32_be() as usize),
+      BlockDelimiter::U32LittleEndian => (4, (&data[..4]).into_buf().get_u32_le() as usize),
+      BlockDelimiter::Varint => match read_varint(data) {
+        Some((prefix, n)) => (prefix, n as usize),
+        None => return Some(SnappyFormat::Uncompressed),
+      },
+    };
+
+    if length == 0 || length > snap::max_compress_len(MAX_SNIFF_BLOCK) {
+      return Some(SnappyFormat::Uncompressed);
+    }
+    if data.len() < prefix + length {
+      return if self.eof() { Some(SnappyFormat::Uncompressed) } else { None };
+    }
+    match snap::Decoder::new().decompress_vec(&data[prefix .. prefix + length]) {
+      Ok(_) => Some(SnappyFormat::Raw),
+      Err(_) => Some(SnappyFormat::Uncompressed),
+    }
+  }
+
+  fn switch(&mut self, format: SnappyFormat) {
+    self.format = Some(format);
+    self.sniffed = BytesMut::new();
+    let rewind = match mem::replace(&mut self.state, State::Empty) {
+      State::Detecting(rewind) => rewind,
+      _ => unreachable!(),
+    };
+    self.state = match format {
+      SnappyFormat::Framed => State::Framed(SnappyUncompress::new(rewind)),
+      SnappyFormat::Xerial => State::Xerial(XerialSnappyUncompress::new(rewind)),
+      SnappyFormat::Hadoop => State::Hadoop(HadoopSnappyUncompress::new(rewind)),
+      SnappyFormat::Raw => State::Raw(SnappyRawUncompress::new(rewind, self.raw_delimiter)),
+      SnappyFormat::Uncompressed => State::Passthrough(rewind),
+    };
+  }
+
+  fn poll_detect(&mut self) -> Poll<(), io::Error> {
+    loop {
+      if let Some(format) = self.detect() {
+        self.switch(format);
+        return Ok(Async::Ready(()));
+      }
+
+      let wanted = self.sniff_limit().saturating_sub(self.sniffed.len());
+      let rewind = match self.state {
+        State::Detecting(ref mut rewind) => rewind,
+        _ => unreachable!(),
+      };
+      match rewind.stream.poll()? {
+        Async::Ready(Some(data)) => {
+          self.sniffed.extend_from_slice(&data[.. cmp::min(data.len(), wanted)]);
+          rewind.buffered.push_back(data);
+        },
+        Async::Ready(None) => rewind.eof = true,
+        Async::NotReady => return Ok(Async::NotReady),
+      }
+    }
+  }
+}
+
+impl<S> Stream for SnappyAutoUncompress<S> where S: ByteStream {
+  type Item = Bytes;
+  type Error = io::Error;
+
+  fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+    if let State::Detecting(_) = self.state {
+      try_ready!(self.poll_detect());
+    }
+
+    match self.state {
+      State::Framed(ref mut s) => s.poll(),
+      State::Xerial(ref mut s) => s.poll(),
+      State::Hadoop(ref mut s) => s.poll(),
+      State::Raw(ref mut s) => s.poll(),
+      State::Passthrough(ref mut s) => s.poll(),
+      State::Detecting(_) | State::Empty => unreachable!(),
+    }
+  }
+}
+
+fn starts_with(data: &[u8], prefix: &[u8]) -> bool {
+  data.len() >= prefix.len() && &data[..prefix.len()] == prefix
+}
+
+// decode a varint from the front of a buffer: (bytes used, value).
+fn read_varint(data: &[u8]) -> Option<(usize, u64)> {
+  let mut n: u64 = 0;
+  for i in 0..5 {
+    if i >= data.len() {
+      return None;
+    }
+    n |= ((data[i] & 0x7f) as u64) << (7 * i);
+    if data[i] & 0x80 == 0 {
+      return Some((i + 1, n));
+    }
+  }
+  None
+}
+
+// hadoop has no header, but the first block is an uncompressed length, a
+// compressed length, and a raw snappy block whose own (varint) length has
+// to be consistent with both.
+fn looks_like_hadoop(data: &[u8]) -> bool {
+  if data.len() < 9 {
+    return false;
+  }
+  let mut buf = (&data[..8]).into_buf();
+  let block_length = buf.get_u32_be() as usize;
+  let chunk_length = buf.get_u32_be() as usize;
+  if !(1..1 << 31).contains(&block_length) || !(2..1 << 31).contains(&chunk_length) {
+    return false;
+  }
+  match read_varint(&data[8..]) {
+    Some((_, n)) => {
+      let n = n as usize;
+      n > 0 && n <= block_length && chunk_length <= snap::max_compress_len(n)
+    },
+    None => false,
+  }
+}
@@ -0,0 +1,74 @@
+use snap;
+use std::io;
+
+use error::{Error};
+use shared::{MAX_BLOCK_SIZE};
+
+/// A block compressor that the framing code can be built on. The streams in
+/// this crate handle buffering, framing, checksums, and backpressure; the
+/// codec only has to turn one block into another.
+///
+/// The API mirrors `snap`'s: the caller asks how big the output might be,
+/// provides a buffer that size, and gets back how much was used.
+pub trait BlockCodec {
+  /// Largest amount of uncompressed data to put in one block.
+  fn max_block_size(&self) -> usize;
+
+  /// Upper bound on the compressed size of `input_len` bytes.
+  fn max_compress_len(&self, input_len: usize) -> usize;
+
+  /// Compress `input` into `output`, which is at least
+  /// `max_compress_len(input.len())` long. Returns the compressed size.
+  fn compress(&mut self, input: &[u8], output: &mut [u8]) -> Result<usize, io::Error>;
+
+  /// The uncompressed size of a compressed block, without decompressing it.
+  fn decompress_len(&self, input: &[u8]) -> Result<usize, io::Error>;
+
+  /// Decompress `input` into `output`, which is at least
+  /// `decompress_len(input)` long. Returns the uncompressed size.
+  fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<usize, io::Error>;
+}
+
+/// The snappy block codec, from the `snap` crate. This is the default codec
+/// everywhere.
+pub struct SnappyCodec {
+  // the encoder carries a large hash table, so it's only built for codecs
+  // that actually compress.
+  encoder: Option<Box<snap::Encoder>>,
+  decoder: snap::Decoder,
+}
+
+impl SnappyCodec {
+  pub fn new() -> SnappyCodec {
+    SnappyCodec { encoder: None, decoder: snap::Decoder::new() }
+  }
+}
+
+impl Default for SnappyCodec {
+  fn default() -> SnappyCodec {
+    SnappyCodec::new()
+  }
+}
+
+impl BlockCodec for SnappyCodec {
+  fn max_block_size(&self) -> usize {
+    MAX_BLOCK_SIZE
+  }
+
+  fn max_compress_len(&self, input_len: usize) -> usize {
+    snap::max_compress_len(input_len)
+  }
+
+  fn compress(&mut self, input: &[u8], output: &mut [u8]) -> Result<usize, io::Error> {
+    let encoder = self.encoder.get_or_insert_with(|| Box::new(snap::Encoder::new()));
+    Ok(encoder.compress(input, output).map_err(Error::Snappy)?)
+  }
+
+  fn decompress_len(&self, input: &[u8]) -> Result<usize, io::Error> {
+    Ok(snap::decompress_len(input).map_err(Error::Snappy)?)
+  }
+
+  fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<usize, io::Error> {
+    Ok(self.decoder.decompress(input, output).map_err(Error::Snappy)?)
+  }
+}
@@ -0,0 +1,222 @@
+use bytes::{Bytes, BytesMut};
+use std::cmp;
+use std::io;
+use std::io::{BufRead, Read, Write};
+
+use compress::{SnappyCompressBuilder};
+use error::{Container, Error, Position};
+use frame::{DecodeEvent, FrameDecoder, FrameEncoder};
+use shared::{MAX_BLOCK_SIZE};
+
+// how much to ask for from the inner reader at a time.
+const READ_SIZE: usize = MAX_BLOCK_SIZE;
+
+/// Blocking version of `SnappyCompress`: an `io::Write` that snappy-frames
+/// everything written to it, and writes the frames to an inner writer.
+///
+/// Data is buffered until there's a full block, so call `flush` to force out
+/// a partial one, and `finish` when done. Dropping the writer will try to
+/// flush, but any error is lost.
+pub struct SnappyWriter<W> where W: Write {
+  inner: Option<W>,
+  encoder: FrameEncoder,
+
+  // uncompressed data that hasn't filled a block yet
+  pending: Vec<u8>,
+
+  // frames that haven't been written to `inner` yet
+  output: BytesMut,
+}
+
+impl<W> SnappyWriter<W> where W: Write {
+  pub fn new(inner: W) -> SnappyWriter<W> {
+    SnappyWriter::with_options(SnappyCompressBuilder::new(), inner)
+  }
+
+  pub(crate) fn with_options(options: SnappyCompressBuilder, inner: W) -> SnappyWriter<W> {
+    SnappyWriter {
+      inner: Some(inner),
+      pending: Vec::with_capacity(options.max_block_size),
+      encoder: FrameEncoder::with_options(options),
+      output: BytesMut::new(),
+    }
+  }
+
+  pub fn get_ref(&self) -> &W {
+    self.inner.as_ref().unwrap()
+  }
+
+  pub fn get_mut(&mut self) -> &mut W {
+    self.inner.as_mut().unwrap()
+  }
+
+  /// Flush any buffered data as a final frame, and return the inner writer.
+  pub fn finish(mut self) -> io::Result<W> {
+    self.flush()?;
+    Ok(self.inner.take().unwrap())
+  }
+
+  // turn whatever's pending into a frame, queued in `output`.
+  fn encode_pending(&mut self) -> io::Result<()> {
+    if let Some(magic) = self.encoder.stream_identifier() {
+      self.output.extend_from_slice(magic.as_ref());
+    }
+    if !self.pending.is_empty() {
+      self.encoder.encode(&self.pending, &mut self.output)?;
+      self.pending.clear();
+    }
+    Ok(())
+  }
+
+  // write out all queued frames. if the inner writer fails (or would
+  // block), whatever's left stays queued for next time.
+  fn write_output(&mut self) -> io::Result<()> {
+    let inner = self.inner.as_mut().unwrap();
+    while !self.output.is_empty() {
+      match inner.write(&self.output) {
+        Ok(0) => return Err(io::Error::new(io::ErrorKind::WriteZero, "Failed to write snappy frame")),
+        Ok(n) => self.output.advance(n),
+        Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+        Err(e) => return Err(e),
+      }
+    }
+    Ok(())
+  }
+}
+
+impl<W> Write for SnappyWriter<W> where W: Write {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    if buf.is_empty() {
+      return Ok(0);
+    }
+    let max_block_size = self.encoder.max_block_size();
+    self.write_output()?;
+    if self.pending.len() >= max_block_size {
+      self.encode_pending()?;
+      self.write_output()?;
+    }
+    let n = cmp::min(buf.len(), max_block_size - self.pending.len());
+    self.pending.extend_from_slice(&buf[..n]);
+    Ok(n)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.encode_pending()?;
+    self.write_output()?;
+    self.inner.as_mut().unwrap().flush()
+  }
+}
+
+impl<W> Drop for SnappyWriter<W> where W: Write {
+  fn drop(&mut self) {
+    if self.inner.is_some() {
+      let _ = self.flush();
+    }
+  }
+}
+
+/// Blocking version of `SnappyUncompress`: an `io::Read` (and `BufRead`)
+/// that reads snappy-framed data from an inner reader and un-frames it.
+pub struct SnappyReader<R> where R: Read {
+  inner: R,
+  decoder: FrameDecoder,
+
+  // data read from `inner` that hasn't been decoded yet
+  input: BytesMut,
+
+  // the most recently decoded block, minus whatever has been consumed
+  block: Bytes,
+}
+
+impl<R> SnappyReader<R> where R: Read {
+  pub fn new(inner: R) -> SnappyReader<R> {
+    SnappyReader {
+      inner,
+      decoder: FrameDecoder::new(),
+      input: BytesMut::new(),
+      block: Bytes::new(),
+    }
+  }
+
+  /// Enforce the framing spec's size limits (default: true). See
+  /// `FrameDecoder::strict`.
+  pub fn strict(mut self, strict: bool) -> SnappyReader<R> {
+    self.decoder = self.decoder.strict(strict);
+    self
+  }
+
+  pub fn get_ref(&self) -> &R {
+    &self.inner
+  }
+
+  pub fn get_mut(&mut self) -> &mut R {
+    &mut self.inner
+  }
+
+  pub fn into_inner(self) -> R {
+    self.inner
+  }
+
+  /// Where the next frame starts in the compressed input.
+  pub fn position(&self) -> Position {
+    self.decoder.position()
+  }
+
+  // decode frames until there's some data in `block`, or we reach the end.
+  fn fill_block(&mut self) -> io::Result<()> {
+    while self.block.is_empty() {
+      match self.decoder.next_event_from(&mut self.input)? {
+        Some(DecodeEvent::Data(data)) => {
+          self.block = data;
+          continue;
+        },
+        // skippable frame, loop around.
+        Some(_) => continue,
+        None => (),
+      }
+
+      // need more. if the read fails (or would block), keep what we have.
+      let len = self.input.len();
+      self.input.resize(len + READ_SIZE, 0);
+      let n = match self.inner.read(&mut self.input[len..]) {
+        Ok(n) => n,
+        Err(e) => {
+          self.input.truncate(len);
+          return Err(e);
+        }
+      };
+      self.input.truncate(len + n);
+      if n == 0 {
+        if self.input.is_empty() {
+          return Ok(());
+        }
+        return Err(self.decoder.locate(Error::Truncated(Container::Framed).into()));
+      }
+    }
+    Ok(())
+  }
+}
+
+impl<R> Read for SnappyReader<R> where R: Read {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let n = {
+      let data = self.fill_buf()?;
+      let n = cmp::min(data.len(), buf.len());
+      buf[..n].copy_from_slice(&data[..n]);
+      n
+    };
+    self.consume(n);
+    Ok(n)
+  }
+}
+
+impl<R> BufRead for SnappyReader<R> where R: Read {
+  fn fill_buf(&mut self) -> io::Result<&[u8]> {
+    self.fill_block()?;
+    Ok(self.block.as_ref())
+  }
+
+  fn consume(&mut self, amt: usize) {
+    self.block.advance(amt);
+  }
+}
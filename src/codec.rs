@@ -0,0 +1,76 @@
+use bytes::{Bytes, BytesMut};
+use std::io;
+use tokio_codec::{Decoder, Encoder};
+
+use compress::{SnappyCompressBuilder};
+use error::{Container, Error};
+use frame::{DecodeEvent, FrameDecoder, FrameEncoder};
+
+/// Codec for the snappy framing format, for use with tokio's `Framed`,
+/// `FramedRead`, and `FramedWrite`.
+///
+/// Decoding yields each frame's uncompressed data as it arrives, with the
+/// same checks as `SnappyUncompress`. Encoding sends the stream identifier
+/// before the first frame, and splits large buffers into blocks, like
+/// `SnappyCompress`.
+pub struct SnappyFrameCodec {
+  encoder: FrameEncoder,
+  decoder: FrameDecoder,
+}
+
+impl SnappyFrameCodec {
+  pub fn new() -> SnappyFrameCodec {
+    SnappyFrameCodec::with_options(SnappyCompressBuilder::new())
+  }
+
+  pub(crate) fn with_options(options: SnappyCompressBuilder) -> SnappyFrameCodec {
+    SnappyFrameCodec {
+      encoder: FrameEncoder::with_options(options),
+      decoder: FrameDecoder::new(),
+    }
+  }
+}
+
+impl Default for SnappyFrameCodec {
+  fn default() -> SnappyFrameCodec {
+    SnappyFrameCodec::new()
+  }
+}
+
+impl Decoder for SnappyFrameCodec {
+  type Item = Bytes;
+  type Error = io::Error;
+
+  fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Bytes>, io::Error> {
+    loop {
+      match self.decoder.next_event_from(src)? {
+        None => return Ok(None),
+        Some(DecodeEvent::Data(data)) => return Ok(Some(data)),
+        // skippable frame, loop around.
+        Some(_) => (),
+      }
+    }
+  }
+
+  fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Bytes>, io::Error> {
+    match self.decode(src)? {
+      Some(data) => Ok(Some(data)),
+      None => {
+        if src.is_empty() {
+          Ok(None)
+        } else {
+          Err(self.decoder.locate(Error::Truncated(Container::Framed).into()))
+        }
+      }
+    }
+  }
+}
+
+impl Encoder for SnappyFrameCodec {
+  type Item = Bytes;
+  type Error = io::Error;
+
+  fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> Result<(), io::Error> {
+    self.encoder.encode(item.as_ref(), dst)
+  }
+}
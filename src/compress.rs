@@ -1,92 +1,337 @@
-use aliases::{ByteStream};
-use bytes::{BufMut, Bytes, BytesMut, LittleEndian};
-use futures::{Async, Poll, Stream};
-use snap;
+use aliases::{ByteSink, ByteStream};
+use bytes::{Bytes, BytesMut};
+use futures::{Async, Future, Poll, Stream};
 use std::io;
+use std::io::Write;
+use std::time::{Duration, Instant};
+use tokio_timer::Delay;
 
-use shared::{crc32c_masked, FrameType};
+use block::{BlockCodec, SnappyCodec};
+use blocking::{SnappyWriter};
+use codec::{SnappyFrameCodec};
+use frame::{FrameEncoder};
+use parallel::{ParallelSnappyCompress, WorkerPool};
+use pool::{BufferPool};
+use shared::{MAX_BLOCK_SIZE};
+use sink::{InputQueue, SnappyCompressSink};
 
-// private inside snap :(
-const MAX_BLOCK_SIZE: usize = 1 << 16;
+// if a compressed block is >= 7/8 of the original size, skip compression.
+const DEFAULT_RATIO_CUTOFF: f64 = 0.875;
 
-lazy_static! {
-  static ref MAX_COMPRESS_BLOCK_SIZE: usize = snap::max_compress_len(MAX_BLOCK_SIZE);
+/// Configuration for a `SnappyCompress` stream. The defaults match
+/// `SnappyCompress::new`.
+#[derive(Clone, Debug)]
+pub struct SnappyCompressBuilder {
+  pub(crate) max_block_size: usize,
+  pub(crate) ratio_cutoff: f64,
+  pub(crate) stream_identifier: bool,
+  pub(crate) checksums: bool,
+  pub(crate) coalesce: bool,
+  pub(crate) max_latency: Option<Duration>,
+  pub(crate) buffer_pool: Option<BufferPool>,
 }
 
-// special snappy stream magic header
-const MAGIC: &'static [u8] = b"\xFF\x06\x00\x00sNaPpY";
+impl SnappyCompressBuilder {
+  pub fn new() -> SnappyCompressBuilder {
+    SnappyCompressBuilder {
+      max_block_size: MAX_BLOCK_SIZE,
+      ratio_cutoff: DEFAULT_RATIO_CUTOFF,
+      stream_identifier: true,
+      checksums: true,
+      coalesce: false,
+      max_latency: None,
+      buffer_pool: None,
+    }
+  }
+
+  /// Largest amount of uncompressed data to put in one frame. The snappy
+  /// framing format forbids frames larger than 64KB, so this is capped at
+  /// `MAX_BLOCK_SIZE`.
+  pub fn max_block_size(mut self, size: usize) -> SnappyCompressBuilder {
+    assert!(size > 0, "max_block_size must be positive");
+    self.max_block_size = if size > MAX_BLOCK_SIZE { MAX_BLOCK_SIZE } else { size };
+    self
+  }
+
+  /// If a compressed block is at least this fraction of its original size,
+  /// send it uncompressed instead. The default is 0.875 (7/8). Use 1.0 to
+  /// always compress unless it makes things bigger, or 0.0 to never compress.
+  pub fn ratio_cutoff(mut self, ratio: f64) -> SnappyCompressBuilder {
+    assert!(ratio >= 0.0 && ratio.is_finite(), "ratio_cutoff must be a non-negative number");
+    self.ratio_cutoff = ratio;
+    self
+  }
+
+  /// Whether to begin the stream with the stream identifier (magic header).
+  /// Turn this off when appending to an existing framed stream.
+  pub fn stream_identifier(mut self, enabled: bool) -> SnappyCompressBuilder {
+    self.stream_identifier = enabled;
+    self
+  }
+
+  /// Whether to compute the CRC of each frame. When it's off, frames carry a
+  /// zero checksum instead, which saves time when the other end doesn't
+  /// check (see `CrcPolicy::Skip`), and fails when it does.
+  pub fn checksums(mut self, enabled: bool) -> SnappyCompressBuilder {
+    self.checksums = enabled;
+    self
+  }
+
+  /// Buffer small incoming chunks until there's a full block to compress,
+  /// instead of emitting one frame per chunk. With this on, the output only
+  /// depends on the data, not on how the input was split up. Anything left
+  /// over is flushed when the input stream ends.
+  ///
+  /// A zero-length chunk in the input acts as a flush marker: whatever is
+  /// buffered is emitted as a (short) frame right away.
+  pub fn coalesce(mut self, enabled: bool) -> SnappyCompressBuilder {
+    self.coalesce = enabled;
+    self
+  }
+
+  /// When coalescing, never hold buffered data for longer than this: if the
+  /// input stalls, emit whatever is buffered once the timer fires. This uses
+  /// `tokio-timer`, so the stream must be polled from inside a tokio runtime.
+  pub fn max_latency(mut self, latency: Duration) -> SnappyCompressBuilder {
+    self.max_latency = Some(latency);
+    self
+  }
+
+  /// Borrow the scratch space for compressing from `pool` each time a block
+  /// is encoded, instead of each encoder keeping its own for life.
+  pub fn buffer_pool(mut self, pool: BufferPool) -> SnappyCompressBuilder {
+    self.buffer_pool = Some(pool);
+    self
+  }
+
+  pub fn build<S>(self, stream: S) -> SnappyCompress<S> where S: ByteStream {
+    self.build_with_codec(stream, SnappyCodec::new())
+  }
+
+  /// Build a compressor that uses `codec` for each block instead of snappy.
+  /// `max_block_size` is also limited by the codec's own maximum.
+  pub fn build_with_codec<S, C>(mut self, stream: S, codec: C) -> SnappyCompress<S, C>
+    where S: ByteStream, C: BlockCodec
+  {
+    let encoder = FrameEncoder::with_codec_options(codec, self.clone());
+    self.max_block_size = encoder.max_block_size();
+    SnappyCompress {
+      stream,
+      encoder,
+      current_buffer: None,
+      pending: BytesMut::new(),
+      flush_requested: false,
+      done: false,
+      deadline: None,
+      options: self,
+    }
+  }
+
+  /// Build a compressor that compresses blocks on the threads in `pool`.
+  /// `max_latency` doesn't apply.
+  pub fn build_parallel<S>(self, stream: S, pool: WorkerPool) -> ParallelSnappyCompress<S> where S: ByteStream {
+    ParallelSnappyCompress::with_options(self, stream, pool)
+  }
+
+  /// Build the runtime-independent encoder core with these options.
+  pub fn build_encoder(self) -> FrameEncoder {
+    FrameEncoder::with_options(self)
+  }
+
+  /// Build a push-mode compressor that writes framed output into `sink`.
+  pub fn build_sink<K>(self, sink: K) -> SnappyCompressSink<K> where K: ByteSink {
+    SnappyCompressSink::from_stream(self.build(InputQueue::new()), sink)
+  }
+
+  /// Build a tokio codec that encodes with these options.
+  pub fn build_codec(self) -> SnappyFrameCodec {
+    SnappyFrameCodec::with_options(self)
+  }
+
+  /// Build a blocking `io::Write` compressor around `writer`. It always
+  /// coalesces, so `coalesce` and `max_latency` don't apply.
+  pub fn build_writer<W>(self, writer: W) -> SnappyWriter<W> where W: Write {
+    SnappyWriter::with_options(self, writer)
+  }
+}
+
+impl Default for SnappyCompressBuilder {
+  fn default() -> SnappyCompressBuilder {
+    SnappyCompressBuilder::new()
+  }
+}
 
-pub struct SnappyCompress<S> where S: ByteStream {
+pub struct SnappyCompress<S, C = SnappyCodec> where S: ByteStream, C: BlockCodec {
   stream: S,
-  encoder: snap::Encoder,
+  encoder: FrameEncoder<C>,
+  options: SnappyCompressBuilder,
 
-  // we can only compress MAX_BLOCK_SIZE at a time, so if we receive a
+  // we can only compress `max_block_size` at a time, so if we receive a
   // larger block, we'll need to generate more than one outbound buffer.
   // this stores the remainder for next time.
   current_buffer: Option<Bytes>,
 
-  output_buffer: Vec<u8>,
+  // in coalescing mode, input that hasn't filled a whole block yet.
+  pending: BytesMut,
+
+  // emit `pending` on the next poll even if it's not a full block.
+  flush_requested: bool,
 
-  // snappy framed streams require a magic header (at least once)
-  sent_magic: bool,
+  // the input stream has ended, so it mustn't be polled again.
+  done: bool,
+
+  // when `max_latency` is set: fires when the oldest pending data is due.
+  deadline: Option<Delay>,
 }
 
 impl<S> SnappyCompress<S> where S: ByteStream {
   pub fn new(stream: S) -> SnappyCompress<S> {
-    let mut s = SnappyCompress {
-      stream,
-      encoder: snap::Encoder::new(),
-      current_buffer: None,
-      output_buffer: Vec::with_capacity(*MAX_COMPRESS_BLOCK_SIZE),
-      sent_magic: false,
-    };
-    // fill the output buffer with zeros for safety.
-    s.output_buffer.resize(*MAX_COMPRESS_BLOCK_SIZE, 0);
-    s
-  }
-
-  fn encode_frame(&mut self, data: Bytes) -> Result<Bytes, snap::Error> {
-    let crc = crc32c_masked(data.as_ref());
-    // this can't really fail, but roll with it:
-    let length = self.encoder.compress(data.as_ref(), &mut self.output_buffer[..])?;
-
-    // if the result is >= 7/8 of the original size, skip compression.
-    if length >= data.len() - (data.len() / 8) {
-      let mut out = BytesMut::with_capacity(data.len() + 8);
-      Self::encode_header(&mut out, FrameType::Uncompressed, data.len() + 4, crc);
-      out.put(data);
-      Ok(out.freeze())
-    } else {
-      let mut out = BytesMut::with_capacity(length + 8);
-      Self::encode_header(&mut out, FrameType::Compressed, length + 4, crc);
-      out.put(&self.output_buffer[..length]);
-      Ok(out.freeze())
+    SnappyCompressBuilder::new().build(stream)
+  }
+}
+
+impl<S, C> SnappyCompress<S, C> where S: ByteStream, C: BlockCodec {
+  pub fn with_codec(stream: S, codec: C) -> SnappyCompress<S, C> {
+    SnappyCompressBuilder::new().build_with_codec(stream, codec)
+  }
+
+  pub fn get_ref(&self) -> &S {
+    &self.stream
+  }
+
+  pub fn get_mut(&mut self) -> &mut S {
+    &mut self.stream
+  }
+
+  /// In coalescing mode, emit any buffered data as a frame on the next
+  /// poll, without waiting for a full block. This doesn't wake a task
+  /// that's waiting on the input stream, so poll again after calling it.
+  pub fn flush(&mut self) {
+    self.flush_requested = true;
+  }
+
+  fn poll_encode(&mut self, data: Bytes) -> Poll<Option<Bytes>, io::Error> {
+    let mut out = BytesMut::new();
+    self.encoder.encode(data.as_ref(), &mut out)?;
+    Ok(Async::Ready(Some(out.freeze())))
+  }
+
+  fn poll_coalesced(&mut self) -> Poll<Option<Bytes>, io::Error> {
+    let max_block_size = self.options.max_block_size;
+    loop {
+      // big buffers that arrive while nothing is pending can be cut into
+      // blocks directly. only the leftover needs to be copied.
+      if let Some(mut buffer) = self.current_buffer.take() {
+        if buffer.len() >= max_block_size {
+          if buffer.len() > max_block_size {
+            self.current_buffer = Some(buffer.split_off(max_block_size));
+          }
+          return self.poll_encode(buffer);
+        }
+        self.pending.extend_from_slice(buffer.as_ref());
+      }
+
+      if self.pending.len() >= max_block_size {
+        let block = self.pending.split_to(max_block_size).freeze();
+        if self.pending.is_empty() { self.deadline = None; }
+        return self.poll_encode(block);
+      }
+
+      if self.flush_requested {
+        self.flush_requested = false;
+        if !self.pending.is_empty() {
+          return self.poll_flush();
+        }
+      }
+
+      if self.done {
+        return Ok(Async::Ready(None));
+      }
+
+      match self.stream.poll() {
+        Ok(Async::Ready(Some(data))) => {
+          if data.is_empty() {
+            // flush marker.
+            self.flush_requested = true;
+          } else if self.pending.is_empty() {
+            self.current_buffer = Some(data);
+            self.start_deadline();
+          } else {
+            self.pending.extend_from_slice(data.as_ref());
+          }
+        },
+        Ok(Async::Ready(None)) => {
+          self.done = true;
+          if self.pending.is_empty() {
+            return Ok(Async::Ready(None));
+          }
+          return self.poll_flush();
+        },
+        Ok(Async::NotReady) => {
+          // nothing new: see if we've held the pending data too long.
+          if self.pending.is_empty() {
+            self.deadline = None;
+            return Ok(Async::NotReady);
+          }
+          let expired = match self.deadline {
+            None => false,
+            Some(ref mut delay) => match delay.poll() {
+              Ok(Async::Ready(())) => true,
+              Ok(Async::NotReady) => false,
+              Err(e) => return Err(io::Error::other(e)),
+            }
+          };
+          if !expired {
+            return Ok(Async::NotReady);
+          }
+          return self.poll_flush();
+        },
+        Err(e) => {
+          return Err(e);
+        }
+      }
     }
   }
 
-  // header: type(1), len_le(3), crc_le(4)
-  fn encode_header(out: &mut BytesMut, chunk_type: FrameType, length: usize, crc: u32) {
-    out.put_uint::<LittleEndian>(chunk_type as u8 as u64, 1);
-    out.put_uint::<LittleEndian>(length as u64, 3);
-    out.put_u32::<LittleEndian>(crc);
+  // emit everything pending as one (probably short) frame.
+  fn poll_flush(&mut self) -> Poll<Option<Bytes>, io::Error> {
+    self.deadline = None;
+    let block = self.pending.take().freeze();
+    self.poll_encode(block)
+  }
+
+  fn start_deadline(&mut self) {
+    if let Some(latency) = self.options.max_latency {
+      self.deadline = Some(Delay::new(Instant::now() + latency));
+    }
   }
 }
 
-impl<S> Stream for SnappyCompress<S> where S: ByteStream {
+impl<S, C> Stream for SnappyCompress<S, C> where S: ByteStream, C: BlockCodec {
   type Item = Bytes;
   type Error = io::Error;
 
   fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
-    if !self.sent_magic {
-      self.sent_magic = true;
-      return Ok(Async::Ready(Some(Bytes::from_static(MAGIC))));
+    if let Some(magic) = self.encoder.stream_identifier() {
+      return Ok(Async::Ready(Some(magic)));
+    }
+
+    if self.options.coalesce {
+      return self.poll_coalesced();
     }
 
     if self.current_buffer.is_none() {
+      if self.done {
+        return Ok(Async::Ready(None));
+      }
       match self.stream.poll() {
         Ok(Async::Ready(Some(data))) => {
           self.current_buffer = Some(data);
         },
+        Ok(Async::Ready(None)) => {
+          self.done = true;
+          return Ok(Async::Ready(None));
+        },
         other => {
           return other;
         }
@@ -94,14 +339,10 @@ impl<S> Stream for SnappyCompress<S> where S: ByteStream {
     }
 
     let mut buffer = self.current_buffer.take().unwrap();
-    if buffer.len() > MAX_BLOCK_SIZE {
-      self.current_buffer = Some(buffer.split_off(MAX_BLOCK_SIZE));
+    if buffer.len() > self.options.max_block_size {
+      self.current_buffer = Some(buffer.split_off(self.options.max_block_size));
     }
 
-    // there shouldn't really be errors here, but handle it just in case.
-    match self.encode_frame(buffer) {
-      Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
-      Ok(out) => Ok(Async::Ready(Some(out)))
-    }
+    self.poll_encode(buffer)
   }
 }
@@ -0,0 +1,251 @@
+use lz4_flex::block::{CompressError, DecompressError};
+use snap;
+use std::error;
+use std::fmt;
+use std::io;
+
+/// Which container format an error came from.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Container {
+  /// The snappy framing format.
+  Framed,
+
+  /// Raw snappy blocks.
+  Raw,
+
+  /// hadoop's snappy block format.
+  Hadoop,
+
+  /// xerial/snappy-java streams.
+  Xerial,
+
+  /// The LZ4 frame format.
+  Lz4,
+}
+
+/// Where a decoder is in its input: the byte offset and (zero-based) index
+/// of a frame. In an error, this is where the bad frame started.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Position {
+  pub offset: u64,
+  pub frame: u64,
+}
+
+impl fmt::Display for Position {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "frame {} at byte {}", self.frame, self.offset)
+  }
+}
+
+/// Everything that can go wrong while decoding (or, rarely, encoding). The
+/// streams in this crate all speak `io::Error`, so these arrive wrapped in
+/// one, and can be pulled back out with `Error::from_io`:
+///
+/// ```ignore
+/// match Error::from_io(&e) {
+///   Some(&Error::CrcMismatch { expected, actual }) => ...,
+///   _ => ...,
+/// }
+/// ```
+pub enum Error {
+  /// The stream didn't start with the format's magic header.
+  MissingMagic(Container),
+
+  /// The magic header was there, but wrong.
+  MangledMagic(Container),
+
+  /// The stream header asks for a newer version of the format.
+  UnsupportedVersion(Container, u32),
+
+  /// The stream ended partway through a frame or block.
+  Truncated(Container),
+
+  /// A snappy frame of a reserved (unskippable) type.
+  UnknownFrameType(u8),
+
+  /// A snappy frame's CRC didn't match its data.
+  CrcMismatch { expected: u32, actual: u32 },
+
+  /// A block length that can't be valid.
+  InvalidLength(Container),
+
+  /// A block that's bigger than the format (or its header) allows.
+  BlockTooLarge(Container),
+
+  /// An LZ4 frame header with reserved bits set, or an unknown block size.
+  InvalidHeader(Container),
+
+  /// An LZ4 frame header's checksum didn't match.
+  HeaderChecksumMismatch,
+
+  /// An LZ4 block's checksum didn't match its data.
+  BlockChecksumMismatch { expected: u32, actual: u32 },
+
+  /// An LZ4 frame's content checksum didn't match its data.
+  ContentChecksumMismatch { expected: u32, actual: u32 },
+
+  /// An LZ4 frame's data wasn't the size its header promised.
+  ContentSizeMismatch { expected: u64, actual: u64 },
+
+  /// An LZ4 frame that needs a preset dictionary.
+  DictionaryUnsupported,
+
+  /// The uncompressed data would be larger than the decoder's `max_output`.
+  OutputLimitExceeded { limit: u64 },
+
+  /// The uncompressed data would be more than `max_ratio` times the size of
+  /// the compressed data.
+  RatioLimitExceeded { ratio: f64 },
+
+  /// A frame is larger than the decoder's `max_buffered`.
+  BufferLimitExceeded { limit: usize },
+
+  /// The snappy block codec failed.
+  Snappy(snap::Error),
+
+  /// The LZ4 block codec failed to compress.
+  Lz4Compress(CompressError),
+
+  /// The LZ4 block codec failed to decompress.
+  Lz4Decompress(DecompressError),
+
+  /// Another error, and where in the stream it happened. `inner` and
+  /// `position` look through this.
+  At(Position, Box<Error>),
+}
+
+impl Error {
+  /// If this `io::Error` came from us, get the original error back out.
+  pub fn from_io(e: &io::Error) -> Option<&Error> {
+    e.get_ref().and_then(|inner| inner.downcast_ref::<Error>())
+  }
+
+  /// The error itself, without its position.
+  pub fn inner(&self) -> &Error {
+    match *self {
+      Error::At(_, ref e) => e.inner(),
+      ref e => e,
+    }
+  }
+
+  /// Where the bad frame started, if the decoder knows.
+  pub fn position(&self) -> Option<Position> {
+    match *self {
+      Error::At(position, _) => Some(position),
+      _ => None,
+    }
+  }
+
+  /// Attach a position to an `io::Error` that came from us. Other errors
+  /// (from a custom `BlockCodec`, for example) pass through untouched.
+  pub(crate) fn locate(e: io::Error, position: Position) -> io::Error {
+    let is_ours = Error::from_io(&e).map(|inner| inner.position().is_none()).unwrap_or(false);
+    if !is_ours {
+      return e;
+    }
+    let inner = e.into_inner().unwrap().downcast::<Error>().unwrap();
+    Error::At(position, inner).into()
+  }
+
+  pub fn kind(&self) -> io::ErrorKind {
+    match *self {
+      Error::At(_, ref e) => e.kind(),
+      Error::Truncated(_) => io::ErrorKind::UnexpectedEof,
+      _ => io::ErrorKind::InvalidData,
+    }
+  }
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Error::MissingMagic(c) => write!(f, "Not {} stream (missing magic header)", stream_name(c)),
+      Error::MangledMagic(c) => write!(f, "Not {} stream (mangled magic header)", stream_name(c)),
+      Error::UnsupportedVersion(Container::Lz4, v) => write!(f, "Unsupported lz4 frame version {}", v),
+      Error::UnsupportedVersion(_, v) => write!(f, "Unsupported xerial snappy version {}", v),
+      Error::Truncated(Container::Raw) => write!(f, "Truncated snappy block"),
+      Error::Truncated(c) => write!(f, "Truncated {}", unit_name(c)),
+      Error::UnknownFrameType(t) => write!(f, "Unknown frame type {}", t),
+      Error::CrcMismatch { expected, actual } => {
+        write!(f, "Frame CRC mismatch: expected {:x}, got {:x}", expected, actual)
+      },
+      Error::InvalidLength(c) => write!(f, "Invalid {} length", unit_name(c)),
+      Error::BlockTooLarge(Container::Hadoop) => write!(f, "Hadoop snappy chunk is larger than its block"),
+      Error::BlockTooLarge(Container::Lz4) => write!(f, "Lz4 block is larger than the maximum block size"),
+      Error::BlockTooLarge(Container::Framed) => write!(f, "Snappy frame is too large"),
+      Error::BlockTooLarge(Container::Raw) => write!(f, "Raw snappy block is too large"),
+      Error::BlockTooLarge(Container::Xerial) => write!(f, "Xerial snappy block is too large"),
+      Error::InvalidHeader(c) => write!(f, "Invalid {} header", unit_name(c)),
+      Error::HeaderChecksumMismatch => write!(f, "Lz4 header checksum mismatch"),
+      Error::BlockChecksumMismatch { expected, actual } => {
+        write!(f, "Lz4 block checksum mismatch: expected {:x}, got {:x}", expected, actual)
+      },
+      Error::ContentChecksumMismatch { expected, actual } => {
+        write!(f, "Lz4 content checksum mismatch: expected {:x}, got {:x}", expected, actual)
+      },
+      Error::ContentSizeMismatch { expected, actual } => {
+        write!(f, "Lz4 content size mismatch: expected {}, got {}", expected, actual)
+      },
+      Error::DictionaryUnsupported => write!(f, "Lz4 dictionary IDs are not supported"),
+      Error::OutputLimitExceeded { limit } => {
+        write!(f, "Uncompressed data exceeds limit of {} bytes", limit)
+      },
+      Error::RatioLimitExceeded { ratio } => write!(f, "Uncompressed data exceeds expansion ratio of {}", ratio),
+      Error::BufferLimitExceeded { limit } => write!(f, "Frame exceeds buffer limit of {} bytes", limit),
+      Error::Snappy(ref e) => e.fmt(f),
+      Error::Lz4Compress(ref e) => e.fmt(f),
+      Error::Lz4Decompress(ref e) => e.fmt(f),
+      Error::At(position, ref e) => write!(f, "{} ({})", e, position),
+    }
+  }
+}
+
+// `unwrap` on an `io::Error` shows the inner error's debug form, and that
+// should still say what went wrong.
+impl fmt::Debug for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Error({})", self)
+  }
+}
+
+impl error::Error for Error {
+  fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+    match *self {
+      Error::Snappy(ref e) => Some(e),
+      Error::Lz4Compress(ref e) => Some(e),
+      Error::Lz4Decompress(ref e) => Some(e),
+      Error::At(_, ref e) => e.source(),
+      _ => None,
+    }
+  }
+}
+
+impl From<snap::Error> for Error {
+  fn from(e: snap::Error) -> Error {
+    Error::Snappy(e)
+  }
+}
+
+impl From<Error> for io::Error {
+  fn from(e: Error) -> io::Error {
+    io::Error::new(e.kind(), e)
+  }
+}
+
+fn stream_name(c: Container) -> &'static str {
+  match c {
+    Container::Xerial => "a xerial snappy",
+    Container::Lz4 => "an lz4",
+    _ => "a snappy",
+  }
+}
+
+fn unit_name(c: Container) -> &'static str {
+  match c {
+    Container::Framed => "snappy frame",
+    Container::Raw => "raw snappy block",
+    Container::Hadoop => "hadoop snappy block",
+    Container::Xerial => "xerial snappy block",
+    Container::Lz4 => "lz4 frame",
+  }
+}
@@ -0,0 +1,681 @@
+use bytes::{Bytes, BytesMut};
+use std::cmp;
+use std::io;
+use std::mem;
+use std::ops::Range;
+
+use block::{BlockCodec, SnappyCodec};
+use compress::{SnappyCompressBuilder};
+use error::{Container, Error, Position};
+use pool::{BufferPool};
+use shared::{
+  check_frame_length, decode_frame, decode_header, encode_frame, verify_crc, ByteQueue, FrameType, ARENA_SIZE,
+  MAGIC, MAX_BLOCK_SIZE, STREAM_IDENTIFIER
+};
+
+// the runtime-independent core of the framing format: no streams, sinks, or
+// readers here, just bytes in and bytes out. every adapter in this crate is
+// built on these.
+
+/// Something that happened while decoding a framed stream.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DecodeEvent {
+  /// Uncompressed data from a compressed or uncompressed frame.
+  Data(Bytes),
+
+  /// A (valid) stream identifier frame.
+  StreamIdentifier,
+
+  /// A padding or reserved-skippable frame was skipped. Carries the frame
+  /// type byte.
+  Skipped(u8),
+
+  /// In recovery mode, this range of the input was damaged and skipped.
+  Damaged(Range<u64>),
+}
+
+/// What a decoder does with each frame's CRC-32C.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CrcPolicy {
+  /// Fail on a mismatch. This is the default.
+  Verify,
+
+  /// Don't compute it at all. Only for trusted input.
+  Skip,
+
+  /// Compute it, but on a mismatch, count it (and call the decoder's
+  /// `on_crc_mismatch` callback), and return the data anyway.
+  Report,
+}
+
+type CrcMismatchCallback = Box<dyn FnMut(&Error) + Send>;
+type DamageCallback = Box<dyn FnMut(Range<u64>, &io::Error) + Send>;
+
+#[derive(PartialEq)]
+enum State {
+  // waiting for a complete frame
+  Frame,
+
+  // skipping damaged data (which began at `start`), looking for a frame
+  // that checks out
+  Resync { start: u64 },
+}
+
+/// Push-style decoder for the snappy framing format. Feed it data with
+/// `push` (or `push_slice`), and pull events out with `next_event` until it
+/// returns `None`, meaning it needs more data. Call `finish` at the end of
+/// the input to check for a truncated frame.
+///
+/// If the data is already in one contiguous `BytesMut`, `next_event_from`
+/// decodes straight out of it instead.
+///
+/// Either way, decoding tries not to copy or allocate: an uncompressed frame
+/// comes back as a slice of the input, unless it spans more than one pushed
+/// buffer, and compressed frames are decoded into a shared output arena
+/// that's reused once the blocks from it have been dropped. Holding on to
+/// one block keeps its whole arena (256KB) alive, so copy out anything you
+/// keep for a long time. With `buffer_pool`, the arena is borrowed from a
+/// shared pool, and given back whenever the decoder runs out of input.
+///
+/// Compressed frames are decoded with a `BlockCodec`, which is snappy unless
+/// you use `with_codec`.
+///
+/// By default the decoder is strict, and holds frames to the limits in the
+/// spec: a frame whose header claims more than one block (64KB
+/// uncompressed), or a stream identifier that isn't 6 bytes, is rejected as
+/// soon as the header arrives, so a corrupt length can't make it buffer up
+/// to 16MB. Compressed blocks are checked the same way before anything is
+/// allocated for them. `strict(false)` turns this off.
+///
+/// Each frame's CRC is checked according to a `CrcPolicy`, which fails on
+/// any mismatch by default.
+///
+/// For untrusted input, `max_output`, `max_ratio`, and `max_buffered` put
+/// limits on the total uncompressed size, the ratio of output to input, and
+/// the size of a frame it will buffer. Each is checked before the memory is
+/// allocated, and fails with its own `Error`.
+///
+/// The decoder counts input bytes and frames as it goes, and decode errors
+/// carry the `Position` of the frame that failed (see `Error::position`).
+///
+/// In recovery mode (`recover`), a bad frame doesn't end the stream.
+/// Instead, the decoder scans forward a byte at a time for the next stream
+/// identifier, or a data frame whose CRC matches, and picks up from there.
+/// The skipped range is reported as a `DecodeEvent::Damaged`, and to the
+/// `on_damage` callback along with the error that started it. Exceeding one
+/// of the limits above is still an error. Recovery only works with `push`,
+/// not `next_event_from`.
+pub struct FrameDecoder<C = SnappyCodec> where C: BlockCodec {
+  codec: C,
+  state: State,
+
+  // buffer incoming data until we have a full frame
+  saved: ByteQueue,
+
+  // space for decoded blocks, and for frames that span more than one
+  // pushed buffer
+  arena: BytesMut,
+  pool: Option<BufferPool>,
+
+  // snappy framed streams require a magic header (at least once)
+  seen_magic: bool,
+
+  // enforce the spec's size limits
+  strict: bool,
+
+  // optional limits for untrusted input
+  max_output: Option<u64>,
+  max_ratio: Option<f64>,
+  max_buffered: Option<usize>,
+
+  // total uncompressed data so far
+  output: u64,
+
+  crc_policy: CrcPolicy,
+  crc_mismatches: u64,
+  on_crc_mismatch: Option<CrcMismatchCallback>,
+
+  // skip over damage instead of failing
+  recover: bool,
+  on_damage: Option<DamageCallback>,
+
+  // the error that started the current damaged range, while resyncing
+  damage: Option<io::Error>,
+
+  // in recovery mode, `finish` was called: resync whatever's left instead
+  // of waiting for more.
+  input_ended: bool,
+
+  // where the next (or current partial) frame starts
+  position: Position,
+}
+
+impl FrameDecoder {
+  pub fn new() -> FrameDecoder {
+    FrameDecoder::with_codec(SnappyCodec::new())
+  }
+}
+
+impl<C> FrameDecoder<C> where C: BlockCodec {
+  pub fn with_codec(codec: C) -> FrameDecoder<C> {
+    FrameDecoder {
+      codec,
+      state: State::Frame,
+      saved: ByteQueue::new(),
+      arena: BytesMut::new(),
+      pool: None,
+      seen_magic: false,
+      strict: true,
+      max_output: None,
+      max_ratio: None,
+      max_buffered: None,
+      output: 0,
+      crc_policy: CrcPolicy::Verify,
+      crc_mismatches: 0,
+      on_crc_mismatch: None,
+      recover: false,
+      on_damage: None,
+      damage: None,
+      input_ended: false,
+      position: Position::default(),
+    }
+  }
+
+  /// Enforce the spec's limits on frame sizes (default: true).
+  pub fn strict(mut self, strict: bool) -> FrameDecoder<C> {
+    self.strict = strict;
+    self
+  }
+
+  /// Fail if the total uncompressed data would be more than `limit` bytes.
+  pub fn max_output(mut self, limit: u64) -> FrameDecoder<C> {
+    self.max_output = Some(limit);
+    self
+  }
+
+  /// Fail if the total uncompressed data would be more than `ratio` times
+  /// the compressed input read so far.
+  pub fn max_ratio(mut self, ratio: f64) -> FrameDecoder<C> {
+    self.max_ratio = Some(ratio);
+    self
+  }
+
+  /// Fail on any frame larger than `limit` bytes, instead of buffering it.
+  pub fn max_buffered(mut self, limit: usize) -> FrameDecoder<C> {
+    self.max_buffered = Some(limit);
+    self
+  }
+
+  /// How to check each frame's CRC (default: `CrcPolicy::Verify`).
+  pub fn crc_policy(mut self, policy: CrcPolicy) -> FrameDecoder<C> {
+    self.crc_policy = policy;
+    self
+  }
+
+  /// Report CRC mismatches to `f` instead of failing. It gets the mismatch
+  /// error, with the position of the frame. This sets the policy to
+  /// `CrcPolicy::Report`.
+  pub fn on_crc_mismatch<F>(mut self, f: F) -> FrameDecoder<C> where F: FnMut(&Error) + Send + 'static {
+    self.crc_policy = CrcPolicy::Report;
+    self.on_crc_mismatch = Some(Box::new(f));
+    self
+  }
+
+  /// How many frames had the wrong CRC, under `CrcPolicy::Report`.
+  pub fn crc_mismatches(&self) -> u64 {
+    self.crc_mismatches
+  }
+
+  /// Borrow output space from `pool` while decoding, and give it back
+  /// whenever there's no more input to decode.
+  pub fn buffer_pool(mut self, pool: BufferPool) -> FrameDecoder<C> {
+    self.pool = Some(pool);
+    self
+  }
+
+  /// Skip over damaged frames instead of failing (default: false).
+  pub fn recover(mut self, recover: bool) -> FrameDecoder<C> {
+    self.recover = recover;
+    self
+  }
+
+  /// Report each damaged range to `f`, with the error that started it. This
+  /// turns on recovery mode.
+  pub fn on_damage<F>(mut self, f: F) -> FrameDecoder<C> where F: FnMut(Range<u64>, &io::Error) + Send + 'static {
+    self.recover = true;
+    self.on_damage = Some(Box::new(f));
+    self
+  }
+
+  pub fn push(&mut self, data: Bytes) {
+    self.saved.push(data);
+  }
+
+  /// Same as `push`, but copies the data.
+  pub fn push_slice(&mut self, data: &[u8]) {
+    self.push(Bytes::from(data));
+  }
+
+  /// How much pushed data hasn't been decoded yet.
+  pub fn buffered(&self) -> usize {
+    self.saved.len()
+  }
+
+  /// Where the next frame starts: the number of input bytes in all frames
+  /// decoded so far, and how many frames that was. A damaged range counts
+  /// as one frame.
+  pub fn position(&self) -> Position {
+    self.position
+  }
+
+  /// Decode the next frame from pushed data, or return `None` if there isn't
+  /// a complete frame yet.
+  pub fn next_event(&mut self) -> Result<Option<DecodeEvent>, io::Error> {
+    self.borrow_arena();
+    let rv = self.decode_next();
+    if let Ok(None) = rv {
+      self.release_arena();
+    }
+    rv
+  }
+
+  fn decode_next(&mut self) -> Result<Option<DecodeEvent>, io::Error> {
+    loop {
+      if let State::Resync { start } = self.state {
+        if !self.resync() {
+          if !self.input_ended {
+            return Ok(None);
+          }
+          // nothing left checks out.
+          self.position.offset += self.saved.len() as u64;
+          self.saved.skip(self.saved.len());
+        }
+        return Ok(Some(self.end_damage(start)));
+      }
+
+      // leave the frame in `saved` until it's decoded, so we can back up
+      // if it's damaged.
+      if self.saved.len() < 4 {
+        if self.input_ended && !self.saved.is_empty() {
+          let e = self.locate(Error::Truncated(Container::Framed).into());
+          self.start_damage(e)?;
+          continue;
+        }
+        return Ok(None);
+      }
+      let (frame_type, length) = self.saved.peek_header();
+      if let Err(e) = self.check_header(frame_type, length) {
+        self.start_damage(e)?;
+        continue;
+      }
+      if self.saved.len() < length + 4 {
+        if self.input_ended {
+          let e = self.locate(Error::Truncated(Container::Framed).into());
+          self.start_damage(e)?;
+          continue;
+        }
+        return Ok(None);
+      }
+      let data = self.saved.peek_at(4, length, &mut self.arena);
+      match self.process_frame(frame_type, length, data) {
+        Ok(event) => {
+          self.saved.skip(length + 4);
+          return Ok(Some(event));
+        },
+        Err(e) => self.start_damage(e)?,
+      }
+    }
+  }
+
+  /// Decode the next frame from the front of a contiguous buffer, or return
+  /// `None` if it doesn't hold a complete frame yet. In that case, `src` is
+  /// grown to fit the rest of the frame. Don't mix this with `push`.
+  pub fn next_event_from(&mut self, src: &mut BytesMut) -> Result<Option<DecodeEvent>, io::Error> {
+    self.borrow_arena();
+    let rv = self.decode_next_from(src);
+    if let Ok(None) = rv {
+      self.release_arena();
+    }
+    rv
+  }
+
+  fn decode_next_from(&mut self, src: &mut BytesMut) -> Result<Option<DecodeEvent>, io::Error> {
+    if src.len() < 4 {
+      return Ok(None);
+    }
+    let (frame_type, length) = decode_header(&src[..4]);
+    self.check_header(frame_type, length)?;
+    if src.len() < length + 4 {
+      let needed = length + 4 - src.len();
+      src.reserve(needed);
+      return Ok(None);
+    }
+    let frame = src.split_to(length + 4).freeze();
+    self.process_frame(frame_type, length, frame.slice_from(4)).map(Some)
+  }
+
+  /// Call when there's no more input: fails if there's a partial frame. In
+  /// recovery mode, the leftovers are resynced instead, so keep calling
+  /// `next_event` until it returns `None` to get any frames (and damage)
+  /// found in them.
+  pub fn finish(&mut self) -> Result<(), io::Error> {
+    self.release_arena();
+    if self.state == State::Frame && self.saved.is_empty() {
+      return Ok(());
+    }
+    if !self.recover {
+      return Err(self.locate(Error::Truncated(Container::Framed).into()));
+    }
+    self.input_ended = true;
+    Ok(())
+  }
+
+  // with a pool, take a fresh arena from it if ours can't hold a block.
+  fn borrow_arena(&mut self) {
+    if let Some(ref pool) = self.pool {
+      if self.arena.capacity() < MAX_BLOCK_SIZE {
+        self.arena = pool.get(ARENA_SIZE);
+      }
+    }
+  }
+
+  // when we run out of input, give the arena back to the pool, or without
+  // one, drop it, so idle decoders don't hold on to one.
+  fn release_arena(&mut self) {
+    let arena = mem::replace(&mut self.arena, BytesMut::new());
+    if let Some(ref pool) = self.pool {
+      if arena.capacity() >= MAX_BLOCK_SIZE {
+        pool.put(arena);
+      }
+    }
+  }
+
+  /// Tag an error with the position of the current frame.
+  pub(crate) fn locate(&self, e: io::Error) -> io::Error {
+    Error::locate(e, self.position)
+  }
+
+  // everything we can check before buffering the frame body.
+  fn check_header(&self, frame_type: Result<FrameType, u8>, length: usize) -> Result<(), io::Error> {
+    check_frame_length(&self.codec, self.strict, frame_type, length).map_err(|e| self.locate(e))?;
+    if let Some(limit) = self.max_buffered {
+      if length + 4 > limit {
+        return Err(self.locate(Error::BufferLimitExceeded { limit }.into()));
+      }
+    }
+    Ok(())
+  }
+
+  // in recovery mode, note the start of a damaged range and back up to just
+  // after the start of the bad frame, to look for a good one. otherwise (or
+  // if it's one of our limits), it's an error.
+  fn start_damage(&mut self, e: io::Error) -> Result<(), io::Error> {
+    let fatal = matches!(
+      Error::from_io(&e).map(Error::inner),
+      Some(&Error::OutputLimitExceeded { .. }) | Some(&Error::RatioLimitExceeded { .. }) |
+        Some(&Error::BufferLimitExceeded { .. })
+    );
+    if !self.recover || fatal {
+      return Err(e);
+    }
+    self.state = State::Resync { start: self.position.offset };
+    self.damage = Some(e);
+    self.saved.skip(1);
+    self.position.offset += 1;
+    Ok(())
+  }
+
+  // skip bytes until the front of `saved` looks like a real frame. returns
+  // false if it needs more data to decide, or once the input has ended, if
+  // nothing left checks out.
+  fn resync(&mut self) -> bool {
+    loop {
+      if self.saved.len() < 4 {
+        return false;
+      }
+      let (frame_type, length) = self.saved.peek_header();
+      let plausible = matches!(
+        frame_type,
+        Ok(FrameType::Stream) | Ok(FrameType::Compressed) | Ok(FrameType::Uncompressed)
+      );
+      if plausible && check_frame_length(&self.codec, true, frame_type, length).is_ok() &&
+        self.max_buffered.map(|limit| length + 4 <= limit).unwrap_or(true)
+      {
+        if self.saved.len() < length + 4 {
+          if !self.input_ended {
+            return false;
+          }
+        } else {
+          let data = self.saved.peek_at(4, length, &mut self.arena);
+          if self.frame_checks_out(frame_type, data) {
+            // a data frame with a good CRC is trusted even if the stream
+            // identifier was lost.
+            self.seen_magic = true;
+            return true;
+          }
+        }
+      }
+      self.saved.skip(1);
+      self.position.offset += 1;
+    }
+  }
+
+  // would this frame decode, with a matching CRC? under `CrcPolicy::Skip`,
+  // a zero CRC is good enough too, so streams written without checksums
+  // can still resync on a data frame, as long as a stream identifier has
+  // vouched for them.
+  fn frame_checks_out(&mut self, frame_type: Result<FrameType, u8>, data: Bytes) -> bool {
+    let skip_zero = self.crc_policy == CrcPolicy::Skip && self.seen_magic;
+    let mut check_crc = |expected: u32, data: &[u8]| {
+      if skip_zero && expected == 0 { Ok(()) } else { verify_crc(expected, data) }
+    };
+    match frame_type {
+      Ok(FrameType::Stream) => data.as_ref() == MAGIC,
+      _ => {
+        let mut seen_magic = true;
+        let arena = &mut self.arena;
+        decode_frame(&mut self.codec, &mut seen_magic, true, &mut check_crc, arena, frame_type, data).is_ok()
+      }
+    }
+  }
+
+  // the damaged range that began at `start` ends here: report it.
+  fn end_damage(&mut self, start: u64) -> DecodeEvent {
+    let range = start .. self.position.offset;
+    let e = self.damage.take().unwrap();
+    if let Some(ref mut f) = self.on_damage {
+      f(range.clone(), &e);
+    }
+    self.state = State::Frame;
+    self.position.frame += 1;
+    DecodeEvent::Damaged(range)
+  }
+
+  // check the output limits against the size this frame claims to
+  // uncompress to, before decoding it. a block too broken to say is left
+  // for `decode_frame` to report.
+  fn check_output(&self, frame_type: Result<FrameType, u8>, length: usize, data: &Bytes) -> Result<(), io::Error> {
+    if self.max_output.is_none() && self.max_ratio.is_none() {
+      return Ok(());
+    }
+    let size = match frame_type {
+      Ok(FrameType::Uncompressed) => data.len() - 4,
+      Ok(FrameType::Compressed) => match self.codec.decompress_len(&data[4..]) {
+        Ok(size) => size,
+        Err(_) => return Ok(()),
+      },
+      _ => return Ok(()),
+    };
+    let output = self.output + size as u64;
+    if let Some(limit) = self.max_output {
+      if output > limit {
+        return Err(Error::OutputLimitExceeded { limit }.into());
+      }
+    }
+    if let Some(ratio) = self.max_ratio {
+      let input = self.position.offset + 4 + length as u64;
+      if output as f64 > input as f64 * ratio {
+        return Err(Error::RatioLimitExceeded { ratio }.into());
+      }
+    }
+    Ok(())
+  }
+
+  fn process_frame(
+    &mut self,
+    frame_type: Result<FrameType, u8>,
+    length: usize,
+    data: Bytes
+  ) -> Result<DecodeEvent, io::Error> {
+    let start = self.position;
+    self.check_output(frame_type, length, &data).map_err(|e| Error::locate(e, start))?;
+    let policy = self.crc_policy;
+    let crc_mismatches = &mut self.crc_mismatches;
+    let on_crc_mismatch = &mut self.on_crc_mismatch;
+    let mut check_crc = |expected: u32, data: &[u8]| {
+      match policy {
+        CrcPolicy::Verify => verify_crc(expected, data),
+        CrcPolicy::Skip => Ok(()),
+        CrcPolicy::Report => {
+          if let Err(e) = verify_crc(expected, data) {
+            *crc_mismatches += 1;
+            if let Some(ref mut f) = *on_crc_mismatch {
+              f(Error::from_io(&Error::locate(e, start)).unwrap());
+            }
+          }
+          Ok(())
+        }
+      }
+    };
+    let decoded = decode_frame(
+      &mut self.codec, &mut self.seen_magic, self.strict, &mut check_crc, &mut self.arena, frame_type, data
+    );
+    let decoded = decoded.map_err(|e| Error::locate(e, start))?;
+    self.position.offset += 4 + length as u64;
+    self.position.frame += 1;
+    match decoded {
+      Some(data) => {
+        self.output += data.len() as u64;
+        Ok(DecodeEvent::Data(data))
+      },
+      None => match frame_type {
+        Ok(FrameType::Stream) => Ok(DecodeEvent::StreamIdentifier),
+        Ok(t) => Ok(DecodeEvent::Skipped(t as u8)),
+        Err(b) => Ok(DecodeEvent::Skipped(b)),
+      }
+    }
+  }
+}
+
+impl Default for FrameDecoder {
+  fn default() -> FrameDecoder {
+    FrameDecoder::new()
+  }
+}
+
+/// Push-style encoder for the snappy framing format. Each call to `encode`
+/// appends frames to an output buffer, starting with the stream identifier
+/// (unless it's turned off in the builder).
+///
+/// It doesn't buffer: every call ends on a frame boundary, so callers that
+/// want full-size blocks need to collect data first.
+///
+/// The scratch space for compressing isn't allocated until the first call
+/// to `encode`. If the builder has a `buffer_pool`, it's borrowed from the
+/// pool for each call instead, and given back afterwards.
+pub struct FrameEncoder<C = SnappyCodec> where C: BlockCodec {
+  codec: C,
+  options: SnappyCompressBuilder,
+  output_buffer: BytesMut,
+
+  // snappy framed streams require a magic header (at least once)
+  sent_magic: bool,
+}
+
+impl FrameEncoder {
+  pub fn new() -> FrameEncoder {
+    FrameEncoder::with_options(SnappyCompressBuilder::new())
+  }
+
+  pub(crate) fn with_options(options: SnappyCompressBuilder) -> FrameEncoder {
+    FrameEncoder::with_codec_options(SnappyCodec::new(), options)
+  }
+}
+
+impl<C> FrameEncoder<C> where C: BlockCodec {
+  /// Encode blocks with `codec` instead of snappy.
+  pub fn with_codec(codec: C) -> FrameEncoder<C> {
+    FrameEncoder::with_codec_options(codec, SnappyCompressBuilder::new())
+  }
+
+  pub(crate) fn with_codec_options(codec: C, mut options: SnappyCompressBuilder) -> FrameEncoder<C> {
+    options.max_block_size = cmp::min(options.max_block_size, codec.max_block_size());
+    FrameEncoder {
+      codec,
+      output_buffer: BytesMut::new(),
+      sent_magic: !options.stream_identifier,
+      options,
+    }
+  }
+
+  pub fn max_block_size(&self) -> usize {
+    self.options.max_block_size
+  }
+
+  /// The stream identifier, if it hasn't been sent yet.
+  pub fn stream_identifier(&mut self) -> Option<Bytes> {
+    if self.sent_magic {
+      None
+    } else {
+      self.sent_magic = true;
+      Some(Bytes::from_static(STREAM_IDENTIFIER))
+    }
+  }
+
+  /// Append `data` to `out` as one or more frames, splitting it into blocks
+  /// of at most `max_block_size`. An empty buffer becomes an empty frame.
+  pub fn encode(&mut self, data: &[u8], out: &mut BytesMut) -> Result<(), io::Error> {
+    if let Some(magic) = self.stream_identifier() {
+      out.extend_from_slice(magic.as_ref());
+    }
+
+    // only as much scratch as the biggest block in `data` needs: with a
+    // pool, it's zero-filled on every call.
+    let block_size = cmp::min(data.len(), self.options.max_block_size);
+    let output_size = self.codec.max_compress_len(block_size);
+    if self.output_buffer.len() < output_size {
+      if let Some(ref pool) = self.options.buffer_pool {
+        self.output_buffer = pool.get(output_size);
+      }
+      // fill the output buffer with zeros for safety.
+      self.output_buffer.resize(output_size, 0);
+    }
+
+    let rv = self.encode_blocks(data, out);
+    if let Some(ref pool) = self.options.buffer_pool {
+      pool.put(mem::replace(&mut self.output_buffer, BytesMut::new()));
+    }
+    rv
+  }
+
+  fn encode_blocks(&mut self, data: &[u8], out: &mut BytesMut) -> Result<(), io::Error> {
+    let mut offset = 0;
+    loop {
+      let end = cmp::min(data.len(), offset + self.options.max_block_size);
+      encode_frame(
+        &mut self.codec, &mut self.output_buffer[..], &data[offset..end], self.options.ratio_cutoff,
+        self.options.checksums, out
+      )?;
+      offset = end;
+      if offset >= data.len() {
+        return Ok(());
+      }
+    }
+  }
+}
+
+impl Default for FrameEncoder {
+  fn default() -> FrameEncoder {
+    FrameEncoder::new()
+  }
+}
@@ -0,0 +1,203 @@
+use aliases::{ByteStream};
+use bytes::{Buf, BufMut, Bytes, BytesMut, IntoBuf};
+use futures::{Async, Poll, Stream};
+use snap;
+use std::io;
+
+use error::{Container, Error};
+use shared::{ByteQueue};
+
+// hadoop's default buffer is 256KB, but it reserves room for the worst-case
+// compression overhead, so the uncompressed blocks are a bit smaller. using
+// the same limit keeps our blocks readable by hadoop.
+pub const HADOOP_BLOCK_SIZE: usize = 256 * 1024 - (256 * 1024 / 6 + 32);
+
+/// Compress a stream into the block format used by hadoop's `SnappyCodec`:
+/// each block is a big-endian u32 uncompressed length, followed by a
+/// big-endian u32 compressed length and the compressed data. There are no
+/// checksums.
+///
+/// Like `SnappyCompress`, each incoming buffer becomes one block, unless
+/// it's too big and has to be split.
+pub struct HadoopSnappyCompress<S> where S: ByteStream {
+  stream: S,
+  encoder: snap::Encoder,
+  block_size: usize,
+
+  // the remainder of a buffer that was too big for one block.
+  current_buffer: Option<Bytes>,
+
+  output_buffer: Vec<u8>,
+
+  // the input stream has ended, so it mustn't be polled again.
+  done: bool,
+}
+
+impl<S> HadoopSnappyCompress<S> where S: ByteStream {
+  pub fn new(stream: S) -> HadoopSnappyCompress<S> {
+    HadoopSnappyCompress::with_block_size(stream, HADOOP_BLOCK_SIZE)
+  }
+
+  pub fn with_block_size(stream: S, block_size: usize) -> HadoopSnappyCompress<S> {
+    assert!(block_size > 0, "block_size must be positive");
+    let output_size = snap::max_compress_len(block_size);
+    let mut s = HadoopSnappyCompress {
+      stream,
+      encoder: snap::Encoder::new(),
+      block_size,
+      current_buffer: None,
+      output_buffer: Vec::with_capacity(output_size),
+      done: false,
+    };
+    // fill the output buffer with zeros for safety.
+    s.output_buffer.resize(output_size, 0);
+    s
+  }
+
+  fn encode_block(&mut self, data: Bytes) -> Result<Bytes, io::Error> {
+    let length = self.encoder.compress(data.as_ref(), &mut self.output_buffer[..])
+      .map_err(Error::Snappy)?;
+    let mut out = BytesMut::with_capacity(length + 8);
+    out.put_u32_be(data.len() as u32);
+    out.put_u32_be(length as u32);
+    out.put(&self.output_buffer[..length]);
+    Ok(out.freeze())
+  }
+}
+
+impl<S> Stream for HadoopSnappyCompress<S> where S: ByteStream {
+  type Item = Bytes;
+  type Error = io::Error;
+
+  fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+    while self.current_buffer.is_none() {
+      if self.done {
+        return Ok(Async::Ready(None));
+      }
+      match self.stream.poll() {
+        Ok(Async::Ready(Some(data))) => {
+          // an empty block would look like a block with no chunks.
+          if !data.is_empty() {
+            self.current_buffer = Some(data);
+          }
+        },
+        Ok(Async::Ready(None)) => {
+          self.done = true;
+        },
+        other => {
+          return other;
+        }
+      }
+    }
+
+    let mut buffer = self.current_buffer.take().unwrap();
+    if buffer.len() > self.block_size {
+      self.current_buffer = Some(buffer.split_off(self.block_size));
+    }
+    self.encode_block(buffer).map(|b| Async::Ready(Some(b)))
+  }
+}
+
+#[derive(PartialEq)]
+enum State {
+  // reading the uncompressed length of a block
+  Block,
+
+  // reading the compressed length of the next chunk in a block
+  ChunkLength { remaining: usize },
+
+  // reading a chunk
+  Chunk { remaining: usize, length: usize },
+}
+
+/// Uncompress a stream in hadoop's `SnappyCodec` block format. Each chunk
+/// of each block becomes one buffer in the output stream.
+pub struct HadoopSnappyUncompress<S> where S: ByteStream {
+  stream: S,
+  decoder: snap::Decoder,
+  state: State,
+
+  // buffer incoming data until we have a full chunk
+  saved: ByteQueue,
+}
+
+impl<S> HadoopSnappyUncompress<S> where S: ByteStream {
+  pub fn new(stream: S) -> HadoopSnappyUncompress<S> {
+    HadoopSnappyUncompress {
+      stream,
+      decoder: snap::Decoder::new(),
+      state: State::Block,
+      saved: ByteQueue::new(),
+    }
+  }
+
+  fn feed(&mut self) -> Option<Poll<Option<Bytes>, io::Error>> {
+    match self.stream.poll() {
+      Ok(Async::Ready(None)) => {
+        if self.state == State::Block && self.saved.is_empty() {
+          Some(Ok(Async::Ready(None)))
+        } else {
+          Some(Err(Error::Truncated(Container::Hadoop).into()))
+        }
+      },
+      Ok(Async::Ready(Some(data))) => {
+        self.saved.push(data);
+        None
+      }
+      other => Some(other)
+    }
+  }
+
+  fn read_u32(&mut self) -> Option<usize> {
+    if self.saved.len() < 4 {
+      None
+    } else {
+      Some(self.saved.drain(4).into_buf().get_u32_be() as usize)
+    }
+  }
+}
+
+impl<S> Stream for HadoopSnappyUncompress<S> where S: ByteStream {
+  type Item = Bytes;
+  type Error = io::Error;
+
+  fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+    loop {
+      let progress = match self.state {
+        State::Block => {
+          self.read_u32().map(|length| {
+            // an empty block has no chunks.
+            if length > 0 { State::ChunkLength { remaining: length } } else { State::Block }
+          })
+        },
+        State::ChunkLength { remaining } => {
+          self.read_u32().map(|length| State::Chunk { remaining, length })
+        },
+        State::Chunk { remaining, length } => {
+          if self.saved.len() < length {
+            None
+          } else {
+            let compressed = self.saved.drain(length);
+            let uncompressed = self.decoder.decompress_vec(compressed.as_ref())
+              .map_err(Error::Snappy)?;
+            if uncompressed.len() > remaining {
+              return Err(Error::BlockTooLarge(Container::Hadoop).into());
+            }
+            let remaining = remaining - uncompressed.len();
+            self.state = if remaining > 0 { State::ChunkLength { remaining } } else { State::Block };
+            return Ok(Async::Ready(Some(Bytes::from(uncompressed))));
+          }
+        }
+      };
+
+      match progress {
+        Some(state) => self.state = state,
+        None => {
+          if let Some(rv) = self.feed() {
+            return rv;
+          }
+        }
+      }
+    }
+  }
+}
@@ -2,17 +2,50 @@
 
 extern crate bytes;
 extern crate crc;
+#[macro_use]
 extern crate futures;
+extern crate lz4_flex;
 extern crate snap;
-
+extern crate tokio_codec;
 #[macro_use]
-extern crate lazy_static;
+extern crate tokio_io;
+extern crate tokio_timer;
+extern crate twox_hash;
 
 pub mod aliases;
+pub mod auto;
+pub mod block;
+pub mod blocking;
+pub mod codec;
 pub mod compress;
+pub mod crc32c;
+pub mod error;
+pub mod frame;
+pub mod hadoop;
+pub mod lz4;
+pub mod parallel;
+pub mod pool;
+pub mod raw;
 pub mod shared;
+pub mod sink;
+pub mod transport;
 pub mod uncompress;
+pub mod xerial;
 
-pub use aliases::{ByteStream};
-pub use compress::{SnappyCompress};
+pub use aliases::{ByteSink, ByteStream};
+pub use auto::{SnappyAutoUncompress, SnappyFormat};
+pub use block::{BlockCodec, SnappyCodec};
+pub use blocking::{SnappyReader, SnappyWriter};
+pub use codec::{SnappyFrameCodec};
+pub use compress::{SnappyCompress, SnappyCompressBuilder};
+pub use error::{Container, Error, Position};
+pub use frame::{CrcPolicy, DecodeEvent, FrameDecoder, FrameEncoder};
+pub use hadoop::{HadoopSnappyCompress, HadoopSnappyUncompress};
+pub use lz4::{Lz4Compress, Lz4Uncompress};
+pub use parallel::{ParallelSnappyCompress, ParallelSnappyUncompress, WorkerPool};
+pub use pool::{BufferPool};
+pub use raw::{BlockDelimiter, SnappyRawCompress, SnappyRawUncompress};
+pub use sink::{SnappyCompressSink, SnappyUncompressSink};
+pub use transport::{SnappyTransport};
 pub use uncompress::{SnappyUncompress};
+pub use xerial::{XerialSnappyCompress, XerialSnappyUncompress};
@@ -0,0 +1,477 @@
+use aliases::{ByteStream};
+use bytes::{Buf, BufMut, Bytes, BytesMut, IntoBuf};
+use futures::{Async, Poll, Stream};
+use lz4_flex::block;
+use std::hash::Hasher;
+use std::io;
+use twox_hash::XxHash32;
+
+use error::{Container, Error};
+use shared::{ByteQueue};
+
+// frame header: magic_le(4), flags(1), block descriptor(1), [content size
+// (8)], [dictionary id (4)], header checksum(1)
+pub const LZ4_MAGIC: u32 = 0x184d2204;
+
+// skippable frames use any magic from 0x184d2a50 to 0x184d2a5f.
+const SKIPPABLE_MAGIC: u32 = 0x184d2a50;
+const SKIPPABLE_MAGIC_MASK: u32 = 0xfffffff0;
+
+const FLAG_VERSION: u8 = 0x40;
+const FLAG_VERSION_MASK: u8 = 0xc0;
+const FLAG_INDEPENDENT: u8 = 0x20;
+const FLAG_BLOCK_CHECKSUM: u8 = 0x10;
+const FLAG_CONTENT_SIZE: u8 = 0x08;
+const FLAG_CONTENT_CHECKSUM: u8 = 0x04;
+const FLAG_RESERVED: u8 = 0x02;
+const FLAG_DICTIONARY_ID: u8 = 0x01;
+
+// a block length with the high bit set is stored uncompressed.
+const BLOCK_UNCOMPRESSED: u32 = 0x80000000;
+
+// linked blocks can refer back this far into previous output.
+const WINDOW_SIZE: usize = 64 * 1024;
+
+pub const LZ4_BLOCK_SIZE: usize = 64 * 1024;
+
+// the block sizes the frame format allows, by their descriptor code.
+const BLOCK_SIZES: [(u8, usize); 4] = [ (4, 64 * 1024), (5, 256 * 1024), (6, 1024 * 1024), (7, 4 * 1024 * 1024) ];
+
+fn xxh32(data: &[u8]) -> u32 {
+  XxHash32::oneshot(0, data)
+}
+
+/// Compress a stream into the LZ4 frame format: a frame header, then a
+/// series of independent LZ4 blocks, then an end mark and (by default) a
+/// checksum of the whole content.
+///
+/// Like `SnappyCompress`, each incoming buffer becomes one block, unless
+/// it's too big and has to be split. Blocks that don't compress are stored
+/// uncompressed.
+pub struct Lz4Compress<S> where S: ByteStream {
+  stream: S,
+  block_size: usize,
+  block_checksums: bool,
+  content_checksum: bool,
+
+  // the remainder of a buffer that was too big for one block.
+  current_buffer: Option<Bytes>,
+
+  output_buffer: Vec<u8>,
+
+  // checksum of everything compressed so far
+  hasher: XxHash32,
+
+  sent_header: bool,
+  sent_end_mark: bool,
+}
+
+impl<S> Lz4Compress<S> where S: ByteStream {
+  pub fn new(stream: S) -> Lz4Compress<S> {
+    Lz4Compress::with_block_size(stream, LZ4_BLOCK_SIZE)
+  }
+
+  /// The frame format only allows blocks of 64KB, 256KB, 1MB, or 4MB, so
+  /// `block_size` is rounded up to one of those (or down to 4MB).
+  pub fn with_block_size(stream: S, block_size: usize) -> Lz4Compress<S> {
+    assert!(block_size > 0, "block_size must be positive");
+    let block_size = BLOCK_SIZES.iter().map(|&(_, size)| size).find(|&size| size >= block_size)
+      .unwrap_or(BLOCK_SIZES[BLOCK_SIZES.len() - 1].1);
+    let output_size = block::get_maximum_output_size(block_size);
+    let mut s = Lz4Compress {
+      stream,
+      block_size,
+      block_checksums: false,
+      content_checksum: true,
+      current_buffer: None,
+      output_buffer: Vec::with_capacity(output_size),
+      hasher: XxHash32::with_seed(0),
+      sent_header: false,
+      sent_end_mark: false,
+    };
+    // fill the output buffer with zeros for safety.
+    s.output_buffer.resize(output_size, 0);
+    s
+  }
+
+  /// Follow each block with a checksum of its (compressed) contents. The
+  /// default is off.
+  pub fn block_checksums(mut self, enabled: bool) -> Lz4Compress<S> {
+    self.block_checksums = enabled;
+    self
+  }
+
+  /// End the frame with a checksum of all the uncompressed data. The
+  /// default is on.
+  pub fn content_checksum(mut self, enabled: bool) -> Lz4Compress<S> {
+    self.content_checksum = enabled;
+    self
+  }
+
+  fn encode_header(&self) -> Bytes {
+    let mut flags = FLAG_VERSION | FLAG_INDEPENDENT;
+    if self.block_checksums { flags |= FLAG_BLOCK_CHECKSUM; }
+    if self.content_checksum { flags |= FLAG_CONTENT_CHECKSUM; }
+    let code = BLOCK_SIZES.iter().find(|&&(_, size)| size == self.block_size).unwrap().0;
+    let descriptor = [ flags, code << 4 ];
+
+    let mut out = BytesMut::with_capacity(7);
+    out.put_u32_le(LZ4_MAGIC);
+    out.put(&descriptor[..]);
+    out.put_u8((xxh32(&descriptor) >> 8) as u8);
+    out.freeze()
+  }
+
+  fn encode_block(&mut self, data: Bytes) -> Result<Bytes, io::Error> {
+    self.hasher.write(data.as_ref());
+    let length = block::compress_into(data.as_ref(), &mut self.output_buffer[..])
+      .map_err(Error::Lz4Compress)?;
+
+    let (header, stored) = if length >= data.len() {
+      (data.len() as u32 | BLOCK_UNCOMPRESSED, data.as_ref())
+    } else {
+      (length as u32, &self.output_buffer[..length])
+    };
+    let mut out = BytesMut::with_capacity(stored.len() + 8);
+    out.put_u32_le(header);
+    out.put(stored);
+    if self.block_checksums {
+      out.put_u32_le(xxh32(stored));
+    }
+    Ok(out.freeze())
+  }
+
+  fn encode_end_mark(&self) -> Bytes {
+    let mut out = BytesMut::with_capacity(8);
+    out.put_u32_le(0);
+    if self.content_checksum {
+      out.put_u32_le(self.hasher.finish_32());
+    }
+    out.freeze()
+  }
+}
+
+impl<S> Stream for Lz4Compress<S> where S: ByteStream {
+  type Item = Bytes;
+  type Error = io::Error;
+
+  fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+    if !self.sent_header {
+      self.sent_header = true;
+      return Ok(Async::Ready(Some(self.encode_header())));
+    }
+
+    // the input has already ended.
+    if self.sent_end_mark {
+      return Ok(Async::Ready(None));
+    }
+
+    while self.current_buffer.is_none() {
+      match self.stream.poll() {
+        Ok(Async::Ready(Some(data))) => {
+          // an empty block would look like the end mark.
+          if !data.is_empty() {
+            self.current_buffer = Some(data);
+          }
+        },
+        Ok(Async::Ready(None)) => {
+          self.sent_end_mark = true;
+          return Ok(Async::Ready(Some(self.encode_end_mark())));
+        },
+        other => {
+          return other;
+        }
+      }
+    }
+
+    let mut buffer = self.current_buffer.take().unwrap();
+    if buffer.len() > self.block_size {
+      self.current_buffer = Some(buffer.split_off(self.block_size));
+    }
+    self.encode_block(buffer).map(|b| Async::Ready(Some(b)))
+  }
+}
+
+
+// what the frame header said about the blocks that follow.
+#[derive(Clone, Copy, PartialEq)]
+struct FrameInfo {
+  independent: bool,
+  block_checksums: bool,
+  content_checksum: bool,
+  content_size: Option<u64>,
+  max_block_size: usize,
+}
+
+#[derive(PartialEq)]
+enum State {
+  // between frames: expecting a magic number (or the end)
+  Magic,
+
+  // reading the rest of a frame header
+  Descriptor,
+
+  // expecting a block length (or the end mark)
+  BlockLength { frame: FrameInfo },
+
+  // reading a block, and its checksum if there is one
+  Block { frame: FrameInfo, length: usize, compressed: bool },
+
+  // reading the content checksum after the end mark
+  ContentChecksum,
+
+  // expecting the length of a skippable frame
+  SkipLength,
+
+  // skipping the rest of a skippable frame
+  Skip { remaining: usize },
+}
+
+/// Uncompress an LZ4 frame stream. Each block becomes one buffer in the
+/// output stream. Concatenated frames and skippable frames are handled, and
+/// header, block, and content checksums are verified when they're present.
+pub struct Lz4Uncompress<S> where S: ByteStream {
+  stream: S,
+  state: State,
+
+  // buffer incoming data until we have a full block
+  saved: ByteQueue,
+
+  // checksum and size of the current frame's content so far
+  hasher: XxHash32,
+  content_length: u64,
+
+  // for linked blocks: the end of the previous output, which the next block
+  // can refer back into.
+  window: Vec<u8>,
+
+  output_buffer: Vec<u8>,
+}
+
+impl<S> Lz4Uncompress<S> where S: ByteStream {
+  pub fn new(stream: S) -> Lz4Uncompress<S> {
+    Lz4Uncompress {
+      stream,
+      state: State::Magic,
+      saved: ByteQueue::new(),
+      hasher: XxHash32::with_seed(0),
+      content_length: 0,
+      window: Vec::new(),
+      output_buffer: Vec::new(),
+    }
+  }
+
+  fn feed(&mut self) -> Option<Poll<Option<Bytes>, io::Error>> {
+    match self.stream.poll() {
+      Ok(Async::Ready(None)) => {
+        if self.state == State::Magic && self.saved.is_empty() {
+          Some(Ok(Async::Ready(None)))
+        } else {
+          Some(Err(Error::Truncated(Container::Lz4).into()))
+        }
+      },
+      Ok(Async::Ready(Some(data))) => {
+        self.saved.push(data);
+        None
+      }
+      other => Some(other)
+    }
+  }
+
+  fn read_u32(&mut self) -> u32 {
+    self.saved.drain(4).into_buf().get_u32_le()
+  }
+
+  fn read_magic(&mut self) -> Result<Option<State>, io::Error> {
+    if self.saved.len() < 4 {
+      return Ok(None);
+    }
+    let magic = self.read_u32();
+    if magic == LZ4_MAGIC {
+      Ok(Some(State::Descriptor))
+    } else if magic & SKIPPABLE_MAGIC_MASK == SKIPPABLE_MAGIC {
+      Ok(Some(State::SkipLength))
+    } else {
+      Err(Error::MissingMagic(Container::Lz4).into())
+    }
+  }
+
+  fn read_descriptor(&mut self) -> Result<Option<State>, io::Error> {
+    if self.saved.len() < 2 {
+      return Ok(None);
+    }
+    let flags = self.saved.get(0);
+    let mut length = 3;
+    if flags & FLAG_CONTENT_SIZE != 0 { length += 8; }
+    if flags & FLAG_DICTIONARY_ID != 0 { length += 4; }
+    if self.saved.len() < length {
+      return Ok(None);
+    }
+    let descriptor = self.saved.drain(length);
+
+    if flags & FLAG_VERSION_MASK != FLAG_VERSION {
+      return Err(Error::UnsupportedVersion(Container::Lz4, (flags >> 6) as u32).into());
+    }
+    if flags & FLAG_RESERVED != 0 || descriptor[1] & 0x8f != 0 {
+      return Err(Error::InvalidHeader(Container::Lz4).into());
+    }
+    if (xxh32(&descriptor[..length - 1]) >> 8) as u8 != descriptor[length - 1] {
+      return Err(Error::HeaderChecksumMismatch.into());
+    }
+    if flags & FLAG_DICTIONARY_ID != 0 {
+      return Err(Error::DictionaryUnsupported.into());
+    }
+    let code = descriptor[1] >> 4;
+    let max_block_size = match BLOCK_SIZES.iter().find(|&&(c, _)| c == code) {
+      Some(&(_, size)) => size,
+      None => return Err(Error::InvalidHeader(Container::Lz4).into()),
+    };
+
+    let content_size = if flags & FLAG_CONTENT_SIZE != 0 {
+      Some(descriptor.slice(2, 10).into_buf().get_u64_le())
+    } else {
+      None
+    };
+    let frame = FrameInfo {
+      independent: flags & FLAG_INDEPENDENT != 0,
+      block_checksums: flags & FLAG_BLOCK_CHECKSUM != 0,
+      content_checksum: flags & FLAG_CONTENT_CHECKSUM != 0,
+      content_size,
+      max_block_size,
+    };
+    self.hasher = XxHash32::with_seed(0);
+    self.content_length = 0;
+    self.window.clear();
+    Ok(Some(State::BlockLength { frame }))
+  }
+
+  fn read_block_length(&mut self, frame: FrameInfo) -> Result<Option<State>, io::Error> {
+    if self.saved.len() < 4 {
+      return Ok(None);
+    }
+    let header = self.read_u32();
+    if header == 0 {
+      return self.end_frame(frame).map(Some);
+    }
+
+    let compressed = header & BLOCK_UNCOMPRESSED == 0;
+    let length = (header & !BLOCK_UNCOMPRESSED) as usize;
+    if length > frame.max_block_size {
+      return Err(Error::BlockTooLarge(Container::Lz4).into());
+    }
+    Ok(Some(State::Block { frame, length, compressed }))
+  }
+
+  // the end mark: check the content size, if we were told it.
+  fn end_frame(&mut self, frame: FrameInfo) -> Result<State, io::Error> {
+    if let Some(size) = frame.content_size {
+      if size != self.content_length {
+        return Err(Error::ContentSizeMismatch { expected: size, actual: self.content_length }.into());
+      }
+    }
+    Ok(if frame.content_checksum { State::ContentChecksum } else { State::Magic })
+  }
+
+  fn read_block(&mut self, frame: FrameInfo, length: usize, compressed: bool) -> Result<Option<Bytes>, io::Error> {
+    let checksum_length = if frame.block_checksums { 4 } else { 0 };
+    if self.saved.len() < length + checksum_length {
+      return Ok(None);
+    }
+    let data = self.saved.drain(length);
+    if frame.block_checksums {
+      let expected = self.read_u32();
+      let actual = xxh32(data.as_ref());
+      if expected != actual {
+        return Err(Error::BlockChecksumMismatch { expected, actual }.into());
+      }
+    }
+
+    let out = if compressed {
+      self.output_buffer.resize(frame.max_block_size, 0);
+      let length = if frame.independent {
+        block::decompress_into(data.as_ref(), &mut self.output_buffer[..])
+      } else {
+        block::decompress_into_with_dict(data.as_ref(), &mut self.output_buffer[..], &self.window)
+      }.map_err(Error::Lz4Decompress)?;
+      Bytes::from(&self.output_buffer[..length])
+    } else {
+      data
+    };
+
+    if !frame.independent {
+      self.window.extend_from_slice(out.as_ref());
+      if self.window.len() > WINDOW_SIZE {
+        let excess = self.window.len() - WINDOW_SIZE;
+        self.window.drain(..excess);
+      }
+    }
+    if frame.content_checksum {
+      self.hasher.write(out.as_ref());
+    }
+    self.content_length += out.len() as u64;
+    Ok(Some(out))
+  }
+
+  fn read_content_checksum(&mut self) -> Result<Option<State>, io::Error> {
+    if self.saved.len() < 4 {
+      return Ok(None);
+    }
+    let expected = self.read_u32();
+    let actual = self.hasher.finish_32();
+    if expected != actual {
+      return Err(Error::ContentChecksumMismatch { expected, actual }.into());
+    }
+    Ok(Some(State::Magic))
+  }
+
+  fn read_skip_length(&mut self) -> Option<State> {
+    if self.saved.len() < 4 {
+      return None;
+    }
+    let length = self.read_u32() as usize;
+    Some(if length == 0 { State::Magic } else { State::Skip { remaining: length } })
+  }
+
+  fn skip(&mut self, remaining: usize) -> Option<State> {
+    let n = if self.saved.len() < remaining { self.saved.len() } else { remaining };
+    if n == 0 {
+      return None;
+    }
+    self.saved.skip(n);
+    Some(if n == remaining { State::Magic } else { State::Skip { remaining: remaining - n } })
+  }
+}
+
+impl<S> Stream for Lz4Uncompress<S> where S: ByteStream {
+  type Item = Bytes;
+  type Error = io::Error;
+
+  fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+    loop {
+      let progress = match self.state {
+        State::Magic => self.read_magic()?,
+        State::Descriptor => self.read_descriptor()?,
+        State::BlockLength { frame } => self.read_block_length(frame)?,
+        State::Block { frame, length, compressed } => {
+          if let Some(data) = self.read_block(frame, length, compressed)? {
+            self.state = State::BlockLength { frame };
+            return Ok(Async::Ready(Some(data)));
+          }
+          None
+        },
+        State::ContentChecksum => self.read_content_checksum()?,
+        State::SkipLength => self.read_skip_length(),
+        State::Skip { remaining } => self.skip(remaining),
+      };
+
+      match progress {
+        Some(state) => self.state = state,
+        None => {
+          if let Some(rv) = self.feed() {
+            return rv;
+          }
+        }
+      }
+    }
+  }
+}
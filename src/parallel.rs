@@ -0,0 +1,412 @@
+use aliases::{ByteStream};
+use bytes::{Bytes, BytesMut};
+use futures::{Async, Future, Poll, Stream};
+use futures::sync::oneshot;
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc;
+use std::thread;
+
+use block::{BlockCodec, SnappyCodec};
+use compress::{SnappyCompressBuilder};
+use error::{Container, Error, Position};
+use shared::{
+  check_frame_length, decode_frame, encode_frame, verify_crc, ByteQueue, FrameType, MAGIC, MAX_BLOCK_SIZE,
+  STREAM_IDENTIFIER
+};
+
+type Job = Box<dyn FnOnce(&mut Worker) + Send>;
+
+// what each worker thread keeps between jobs.
+struct Worker {
+  codec: SnappyCodec,
+  scratch: Vec<u8>,
+  arena: BytesMut,
+}
+
+/// A pool of threads that compress (or decompress) blocks for the parallel
+/// streams. Clone it to share the threads between streams. They exit once
+/// every handle, and every stream using them, is gone.
+#[derive(Clone)]
+pub struct WorkerPool {
+  sender: Arc<Mutex<mpsc::Sender<Job>>>,
+  threads: usize,
+}
+
+impl WorkerPool {
+  pub fn new(threads: usize) -> WorkerPool {
+    assert!(threads > 0, "WorkerPool needs at least one thread");
+    let (sender, receiver) = mpsc::channel::<Job>();
+    let receiver = Arc::new(Mutex::new(receiver));
+    for i in 0 .. threads {
+      let receiver = receiver.clone();
+      thread::Builder::new().name(format!("gingersnap-{}", i)).spawn(move || {
+        let mut worker = Worker { codec: SnappyCodec::new(), scratch: Vec::new(), arena: BytesMut::new() };
+        loop {
+          // hold the lock only long enough to take one job.
+          let job = match receiver.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => return,
+          };
+          job(&mut worker);
+        }
+      }).expect("Can't spawn worker thread");
+    }
+    WorkerPool { sender: Arc::new(Mutex::new(sender)), threads }
+  }
+
+  pub fn threads(&self) -> usize {
+    self.threads
+  }
+
+  // run `f` on a worker thread, and get its result back as a future.
+  fn run<F, T>(&self, f: F) -> oneshot::Receiver<T>
+    where F: FnOnce(&mut Worker) -> T + Send + 'static, T: Send + 'static
+  {
+    let (tx, rx) = oneshot::channel();
+    let job: Job = Box::new(move |worker: &mut Worker| {
+      let _ = tx.send(f(worker));
+    });
+    // if the threads are gone, dropping the job cancels `rx`.
+    let _ = self.sender.lock().unwrap().send(job);
+    rx
+  }
+}
+
+// wait for the oldest job. a job that never finished (its worker panicked)
+// is an error.
+fn poll_job<T>(job: &mut oneshot::Receiver<Result<T, io::Error>>) -> Poll<T, io::Error> {
+  match job.poll() {
+    Ok(Async::Ready(rv)) => rv.map(Async::Ready),
+    Ok(Async::NotReady) => Ok(Async::NotReady),
+    Err(_) => Err(io::Error::other("Worker thread failed")),
+  }
+}
+
+/// Snappy-frame a stream the way `SnappyCompress` does, but compress the
+/// blocks on a `WorkerPool`, several at a time. Frames come out in order,
+/// and the output is the same, byte for byte, as `SnappyCompress` with the
+/// same options. (`max_latency` isn't supported.)
+///
+/// At most `max_in_flight` blocks (by default, twice the number of threads)
+/// are being compressed or waiting to be sent at once, which bounds the
+/// memory it uses. Input isn't read while that many are outstanding.
+pub struct ParallelSnappyCompress<S> where S: ByteStream {
+  stream: S,
+  pool: WorkerPool,
+  options: SnappyCompressBuilder,
+  max_in_flight: usize,
+  sent_magic: bool,
+
+  // remainder of an input buffer larger than one block
+  current_buffer: Option<Bytes>,
+
+  // in coalescing mode, input that hasn't filled a whole block yet.
+  pending: BytesMut,
+
+  // the input stream has ended, so it mustn't be polled again.
+  input_done: bool,
+
+  // every block has been submitted.
+  done: bool,
+
+  // blocks being compressed, oldest first.
+  in_flight: VecDeque<oneshot::Receiver<Result<Bytes, io::Error>>>,
+}
+
+impl<S> ParallelSnappyCompress<S> where S: ByteStream {
+  pub fn new(stream: S, pool: WorkerPool) -> ParallelSnappyCompress<S> {
+    SnappyCompressBuilder::new().build_parallel(stream, pool)
+  }
+
+  pub(crate) fn with_options(options: SnappyCompressBuilder, stream: S, pool: WorkerPool) -> ParallelSnappyCompress<S> {
+    ParallelSnappyCompress {
+      stream,
+      max_in_flight: pool.threads() * 2,
+      pool,
+      sent_magic: !options.stream_identifier,
+      options,
+      current_buffer: None,
+      pending: BytesMut::new(),
+      input_done: false,
+      done: false,
+      in_flight: VecDeque::new(),
+    }
+  }
+
+  /// Most blocks to have in progress (or waiting to be sent) at once.
+  pub fn max_in_flight(mut self, max: usize) -> ParallelSnappyCompress<S> {
+    assert!(max > 0, "max_in_flight must be positive");
+    self.max_in_flight = max;
+    self
+  }
+
+  pub fn get_ref(&self) -> &S {
+    &self.stream
+  }
+
+  pub fn get_mut(&mut self) -> &mut S {
+    &mut self.stream
+  }
+
+  // the next block to compress, cut up the same way `SnappyCompress` does.
+  fn poll_block(&mut self) -> Poll<Option<Bytes>, io::Error> {
+    let max_block_size = self.options.max_block_size;
+    loop {
+      if let Some(mut buffer) = self.current_buffer.take() {
+        if buffer.len() > max_block_size {
+          self.current_buffer = Some(buffer.split_off(max_block_size));
+        } else if self.options.coalesce && buffer.len() < max_block_size {
+          self.pending.extend_from_slice(buffer.as_ref());
+          continue;
+        }
+        return Ok(Async::Ready(Some(buffer)));
+      }
+
+      if self.pending.len() >= max_block_size {
+        return Ok(Async::Ready(Some(self.pending.split_to(max_block_size).freeze())));
+      }
+
+      if self.input_done {
+        return Ok(Async::Ready(None));
+      }
+
+      match try_ready!(self.stream.poll()) {
+        Some(data) => {
+          if !self.options.coalesce {
+            self.current_buffer = Some(data);
+          } else if data.is_empty() {
+            // flush marker.
+            if !self.pending.is_empty() {
+              return Ok(Async::Ready(Some(self.pending.take().freeze())));
+            }
+          } else if self.pending.is_empty() {
+            self.current_buffer = Some(data);
+          } else {
+            self.pending.extend_from_slice(data.as_ref());
+          }
+        },
+        None => {
+          self.input_done = true;
+          if self.pending.is_empty() {
+            return Ok(Async::Ready(None));
+          }
+          return Ok(Async::Ready(Some(self.pending.take().freeze())));
+        }
+      }
+    }
+  }
+
+  fn submit(&mut self, block: Bytes) {
+    let ratio_cutoff = self.options.ratio_cutoff;
+    let checksums = self.options.checksums;
+    let job = self.pool.run(move |worker: &mut Worker| {
+      let size = worker.codec.max_compress_len(MAX_BLOCK_SIZE);
+      if worker.scratch.len() < size {
+        worker.scratch.resize(size, 0);
+      }
+      let mut out = BytesMut::with_capacity(block.len() + 8);
+      encode_frame(&mut worker.codec, &mut worker.scratch, &block, ratio_cutoff, checksums, &mut out)?;
+      Ok(out.freeze())
+    });
+    self.in_flight.push_back(job);
+  }
+}
+
+impl<S> Stream for ParallelSnappyCompress<S> where S: ByteStream {
+  type Item = Bytes;
+  type Error = io::Error;
+
+  fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+    if !self.sent_magic {
+      self.sent_magic = true;
+      return Ok(Async::Ready(Some(Bytes::from_static(STREAM_IDENTIFIER))));
+    }
+
+    // keep the workers busy.
+    while !self.done && self.in_flight.len() < self.max_in_flight {
+      match self.poll_block()? {
+        Async::Ready(Some(block)) => self.submit(block),
+        Async::Ready(None) => self.done = true,
+        Async::NotReady => break,
+      }
+    }
+
+    let frame = match self.in_flight.front_mut() {
+      None => return Ok(if self.done { Async::Ready(None) } else { Async::NotReady }),
+      Some(job) => try_ready!(poll_job(job)),
+    };
+    self.in_flight.pop_front();
+    Ok(Async::Ready(Some(frame)))
+  }
+}
+
+// a frame on its way out of `ParallelSnappyUncompress`.
+enum Pending {
+  Job(oneshot::Receiver<Result<Bytes, io::Error>>),
+  Failed(io::Error),
+}
+
+/// Un-frame a snappy stream the way `SnappyUncompress` does, but decompress
+/// and check the frames on a `WorkerPool`, several at a time. Frames are
+/// split up as they arrive, and their data comes out in order.
+///
+/// Errors are reported just as they would be one frame at a time: the
+/// first bad frame in the stream is the one that fails, after all the data
+/// before it. At most `max_in_flight` frames (by default, twice the number
+/// of threads) are being decoded or waiting to be sent at once.
+///
+/// The decoder limits, CRC policies, and recovery mode of `FrameDecoder`
+/// aren't supported here.
+pub struct ParallelSnappyUncompress<S> where S: ByteStream {
+  stream: S,
+  pool: WorkerPool,
+  max_in_flight: usize,
+  strict: bool,
+
+  // only used to check frame lengths
+  codec: SnappyCodec,
+
+  // buffer incoming data until we have a full frame
+  saved: ByteQueue,
+  arena: BytesMut,
+  seen_magic: bool,
+  position: Position,
+
+  // no more frames will be started: the input has ended, or had an error.
+  stopped: bool,
+
+  // frames being decoded, oldest first.
+  in_flight: VecDeque<Pending>,
+}
+
+impl<S> ParallelSnappyUncompress<S> where S: ByteStream {
+  pub fn new(stream: S, pool: WorkerPool) -> ParallelSnappyUncompress<S> {
+    ParallelSnappyUncompress {
+      stream,
+      max_in_flight: pool.threads() * 2,
+      pool,
+      strict: true,
+      codec: SnappyCodec::new(),
+      saved: ByteQueue::new(),
+      arena: BytesMut::new(),
+      seen_magic: false,
+      position: Position::default(),
+      stopped: false,
+      in_flight: VecDeque::new(),
+    }
+  }
+
+  /// Most frames to have in progress (or waiting to be sent) at once.
+  pub fn max_in_flight(mut self, max: usize) -> ParallelSnappyUncompress<S> {
+    assert!(max > 0, "max_in_flight must be positive");
+    self.max_in_flight = max;
+    self
+  }
+
+  /// Enforce the framing spec's size limits (default: true). See
+  /// `FrameDecoder::strict`.
+  pub fn strict(mut self, strict: bool) -> ParallelSnappyUncompress<S> {
+    self.strict = strict;
+    self
+  }
+
+  pub fn get_ref(&self) -> &S {
+    &self.stream
+  }
+
+  pub fn get_mut(&mut self) -> &mut S {
+    &mut self.stream
+  }
+
+  // errors found while splitting frames wait their turn behind the frames
+  // before them.
+  fn fail(&mut self, e: io::Error) {
+    let e = Error::locate(e, self.position);
+    self.in_flight.push_back(Pending::Failed(e));
+    self.stopped = true;
+  }
+
+  // split off whole frames and start decoding them, until there are
+  // `max_in_flight` or we run out of input.
+  fn start_frames(&mut self) {
+    while !self.stopped && self.in_flight.len() < self.max_in_flight {
+      if self.saved.len() >= 4 {
+        let (frame_type, length) = self.saved.peek_header();
+        if let Err(e) = check_frame_length(&self.codec, self.strict, frame_type, length) {
+          self.fail(e);
+          return;
+        }
+        if self.saved.len() >= length + 4 {
+          let data = self.saved.peek_at(4, length, &mut self.arena);
+          self.start_frame(frame_type, data);
+          self.saved.skip(length + 4);
+          self.position.offset += 4 + length as u64;
+          self.position.frame += 1;
+          continue;
+        }
+      }
+
+      match self.stream.poll() {
+        Ok(Async::Ready(Some(data))) => self.saved.push(data),
+        Ok(Async::Ready(None)) => {
+          if self.saved.is_empty() {
+            self.stopped = true;
+          } else {
+            self.fail(Error::Truncated(Container::Framed).into());
+          }
+        },
+        Ok(Async::NotReady) => return,
+        Err(e) => self.fail(e),
+      }
+    }
+  }
+
+  fn start_frame(&mut self, frame_type: Result<FrameType, u8>, data: Bytes) {
+    if !self.seen_magic && frame_type != Ok(FrameType::Stream) {
+      return self.fail(Error::MissingMagic(Container::Framed).into());
+    }
+    match frame_type {
+      Ok(FrameType::Stream) => {
+        if data.as_ref() != MAGIC {
+          return self.fail(Error::MangledMagic(Container::Framed).into());
+        }
+        self.seen_magic = true;
+      },
+      Ok(FrameType::Compressed) | Ok(FrameType::Uncompressed) => {
+        let strict = self.strict;
+        let position = self.position;
+        let job = self.pool.run(move |worker: &mut Worker| {
+          let mut seen_magic = true;
+          let decoded = decode_frame(
+            &mut worker.codec, &mut seen_magic, strict, &mut verify_crc, &mut worker.arena, frame_type, data
+          );
+          decoded.map(|data| data.unwrap_or_default()).map_err(|e| Error::locate(e, position))
+        });
+        self.in_flight.push_back(Pending::Job(job));
+      },
+      // anything else can be skipped:
+      _ => (),
+    }
+  }
+}
+
+impl<S> Stream for ParallelSnappyUncompress<S> where S: ByteStream {
+  type Item = Bytes;
+  type Error = io::Error;
+
+  fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+    self.start_frames();
+    let data = match self.in_flight.front_mut() {
+      None => return Ok(if self.stopped { Async::Ready(None) } else { Async::NotReady }),
+      Some(&mut Pending::Job(ref mut job)) => try_ready!(poll_job(job)),
+      Some(&mut Pending::Failed(_)) => match self.in_flight.pop_front() {
+        Some(Pending::Failed(e)) => return Err(e),
+        _ => unreachable!(),
+      },
+    };
+    self.in_flight.pop_front();
+    Ok(Async::Ready(Some(data)))
+  }
+}
@@ -0,0 +1,57 @@
+use bytes::{BytesMut};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// A pool of buffers that encoders and decoders can share, so idle streams
+/// don't each hold on to their own scratch and output space. Clone it to
+/// get another handle to the same pool.
+///
+/// It's bounded: it holds at most `max_buffers` idle buffers, and anything
+/// handed back past that is dropped. That limits how many buffers it keeps,
+/// not how many bytes: each one is as big as the largest use it's had. Streams only take a buffer when they
+/// have work to do, and give it back when they run out of input or finish.
+#[derive(Clone)]
+pub struct BufferPool {
+  buffers: Arc<Mutex<Vec<BytesMut>>>,
+  max_buffers: usize,
+}
+
+impl BufferPool {
+  pub fn new(max_buffers: usize) -> BufferPool {
+    BufferPool { buffers: Arc::new(Mutex::new(Vec::new())), max_buffers }
+  }
+
+  /// Take an empty buffer with room for at least `capacity` bytes: one
+  /// from the pool if there are any, or a new one.
+  pub fn get(&self, capacity: usize) -> BytesMut {
+    let mut buffer = self.buffers.lock().unwrap().pop().unwrap_or_default();
+    if buffer.capacity() < capacity {
+      buffer.reserve(capacity);
+    }
+    buffer
+  }
+
+  /// Give a buffer back. Its contents are discarded.
+  pub fn put(&self, mut buffer: BytesMut) {
+    buffer.clear();
+    let mut buffers = self.buffers.lock().unwrap();
+    if buffers.len() < self.max_buffers {
+      buffers.push(buffer);
+    }
+  }
+
+  /// How many idle buffers are in the pool.
+  pub fn len(&self) -> usize {
+    self.buffers.lock().unwrap().len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
+
+impl fmt::Debug for BufferPool {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "BufferPool({}/{})", self.len(), self.max_buffers)
+  }
+}
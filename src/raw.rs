@@ -0,0 +1,232 @@
+use aliases::{ByteStream};
+use bytes::{Buf, BufMut, Bytes, BytesMut, IntoBuf};
+use futures::{Async, Poll, Stream};
+use snap;
+use std::io;
+
+use error::{Container, Error};
+use shared::{encode_varint, ByteQueue};
+
+/// How raw snappy blocks are separated from each other in a stream.
+///
+/// A raw block only records its uncompressed length, so without some kind
+/// of delimiter, a stream can only hold one block.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BlockDelimiter {
+  /// The whole stream is a single block.
+  None,
+
+  /// Each block is preceded by its compressed length, as a big-endian u32.
+  U32BigEndian,
+
+  /// Each block is preceded by its compressed length, as a little-endian u32.
+  U32LittleEndian,
+
+  /// Each block is preceded by its compressed length, as a varint (the same
+  /// encoding snappy uses for its own length preamble).
+  Varint,
+}
+
+/// Compress a stream into raw snappy blocks (with no framing or CRCs), one
+/// block per incoming buffer, as `snap::Encoder` would produce. Each block
+/// is preceded by a length, as chosen by `delimiter`.
+pub struct SnappyRawCompress<S> where S: ByteStream {
+  stream: S,
+  encoder: snap::Encoder,
+  delimiter: BlockDelimiter,
+  output_buffer: Vec<u8>,
+
+  // with no delimiter, everything is collected into one block.
+  whole: BytesMut,
+  done: bool,
+}
+
+impl<S> SnappyRawCompress<S> where S: ByteStream {
+  pub fn new(stream: S, delimiter: BlockDelimiter) -> SnappyRawCompress<S> {
+    SnappyRawCompress {
+      stream,
+      encoder: snap::Encoder::new(),
+      delimiter,
+      output_buffer: Vec::new(),
+      whole: BytesMut::new(),
+      done: false,
+    }
+  }
+
+  fn encode_block(&mut self, data: &[u8]) -> Result<Bytes, io::Error> {
+    let max_length = snap::max_compress_len(data.len());
+    if max_length == 0 {
+      return Err(io::Error::new(io::ErrorKind::InvalidInput, "Block too large for snappy"));
+    }
+    if self.output_buffer.len() < max_length {
+      self.output_buffer.resize(max_length, 0);
+    }
+    let length = self.encoder.compress(data, &mut self.output_buffer[..])
+      .map_err(Error::Snappy)?;
+
+    let mut out = BytesMut::with_capacity(length + 5);
+    match self.delimiter {
+      BlockDelimiter::None => (),
+      BlockDelimiter::U32BigEndian => out.put_u32_be(length as u32),
+      BlockDelimiter::U32LittleEndian => out.put_u32_le(length as u32),
+      BlockDelimiter::Varint => encode_varint(&mut out, length as u64),
+    }
+    out.put(&self.output_buffer[..length]);
+    Ok(out.freeze())
+  }
+}
+
+impl<S> Stream for SnappyRawCompress<S> where S: ByteStream {
+  type Item = Bytes;
+  type Error = io::Error;
+
+  fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+    loop {
+      if self.done {
+        return Ok(Async::Ready(None));
+      }
+      match self.stream.poll()? {
+        Async::Ready(Some(data)) => {
+          if self.delimiter == BlockDelimiter::None {
+            self.whole.extend_from_slice(data.as_ref());
+          } else {
+            return self.encode_block(data.as_ref()).map(|b| Async::Ready(Some(b)));
+          }
+        },
+        Async::Ready(None) => {
+          self.done = true;
+          if self.delimiter == BlockDelimiter::None && !self.whole.is_empty() {
+            let whole = self.whole.take();
+            return self.encode_block(whole.as_ref()).map(|b| Async::Ready(Some(b)));
+          }
+        },
+        Async::NotReady => return Ok(Async::NotReady),
+      }
+    }
+  }
+}
+
+#[derive(PartialEq)]
+enum State {
+  // reading the block length
+  Length,
+
+  // reading a block
+  Body { length: usize },
+}
+
+/// Uncompress a stream of raw snappy blocks, separated as described by
+/// `delimiter`. Each block becomes one buffer in the output stream.
+pub struct SnappyRawUncompress<S> where S: ByteStream {
+  stream: S,
+  decoder: snap::Decoder,
+  delimiter: BlockDelimiter,
+  state: State,
+
+  // buffer incoming data until we have a full block
+  saved: ByteQueue,
+  done: bool,
+}
+
+impl<S> SnappyRawUncompress<S> where S: ByteStream {
+  pub fn new(stream: S, delimiter: BlockDelimiter) -> SnappyRawUncompress<S> {
+    SnappyRawUncompress {
+      stream,
+      decoder: snap::Decoder::new(),
+      delimiter,
+      state: State::Length,
+      saved: ByteQueue::new(),
+      done: false,
+    }
+  }
+
+  // try to read a block length from the saved data.
+  fn read_length(&mut self) -> Result<Option<usize>, io::Error> {
+    match self.delimiter {
+      BlockDelimiter::None => Ok(None),
+      BlockDelimiter::U32BigEndian | BlockDelimiter::U32LittleEndian => {
+        if self.saved.len() < 4 {
+          return Ok(None);
+        }
+        let mut buf = self.saved.drain(4).into_buf();
+        if self.delimiter == BlockDelimiter::U32BigEndian {
+          Ok(Some(buf.get_u32_be() as usize))
+        } else {
+          Ok(Some(buf.get_u32_le() as usize))
+        }
+      },
+      BlockDelimiter::Varint => {
+        // a u32 takes at most 5 bytes.
+        let mut length: u64 = 0;
+        for i in 0..5 {
+          if i >= self.saved.len() {
+            return Ok(None);
+          }
+          let b = self.saved.get(i);
+          length |= ((b & 0x7f) as u64) << (7 * i);
+          if b & 0x80 == 0 {
+            self.saved.drain(i + 1);
+            return Ok(Some(length as usize));
+          }
+        }
+        Err(Error::InvalidLength(Container::Raw).into())
+      },
+    }
+  }
+
+  fn decode_block(&mut self, data: Bytes) -> Poll<Option<Bytes>, io::Error> {
+    match self.decoder.decompress_vec(data.as_ref()) {
+      Err(e) => Err(Error::Snappy(e).into()),
+      Ok(uncompressed) => Ok(Async::Ready(Some(Bytes::from(uncompressed)))),
+    }
+  }
+}
+
+impl<S> Stream for SnappyRawUncompress<S> where S: ByteStream {
+  type Item = Bytes;
+  type Error = io::Error;
+
+  fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+    loop {
+      if self.done {
+        return Ok(Async::Ready(None));
+      }
+
+      match self.state {
+        State::Length => {
+          if let Some(length) = self.read_length()? {
+            self.state = State::Body { length };
+            continue;
+          }
+        },
+        State::Body { length } => {
+          if self.saved.len() >= length {
+            let data = self.saved.drain(length);
+            self.state = State::Length;
+            return self.decode_block(data);
+          }
+        }
+      }
+
+      match self.stream.poll()? {
+        Async::Ready(Some(data)) => self.saved.push(data),
+        Async::Ready(None) => {
+          self.done = true;
+          if self.delimiter == BlockDelimiter::None {
+            if self.saved.is_empty() {
+              return Ok(Async::Ready(None));
+            }
+            let length = self.saved.len();
+            let data = self.saved.drain(length);
+            return self.decode_block(data);
+          }
+          if self.state != State::Length || !self.saved.is_empty() {
+            return Err(Error::Truncated(Container::Raw).into());
+          }
+          return Ok(Async::Ready(None));
+        },
+        Async::NotReady => return Ok(Async::NotReady),
+      }
+    }
+  }
+}
@@ -1,5 +1,25 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut, IntoBuf};
+use std::cmp;
+use std::collections::VecDeque;
 use std::convert::TryFrom;
-use crc::crc32;
+use std::io;
+
+use block::{BlockCodec};
+use crc32c::{crc32c};
+use error::{Container, Error};
+
+// private inside snap :(
+pub const MAX_BLOCK_SIZE: usize = 1 << 16;
+
+// decoded blocks are carved out of an output arena this big, so they don't
+// each need their own allocation. once every block from an arena has been
+// dropped, it can be reused. decoders let go of theirs whenever they run
+// out of input, so idle ones don't pin one.
+pub const ARENA_SIZE: usize = 4 * MAX_BLOCK_SIZE;
+
+// special snappy stream magic header, as a whole frame, and just the body.
+pub const STREAM_IDENTIFIER: &[u8] = b"\xFF\x06\x00\x00sNaPpY";
+pub const MAGIC: &[u8] = b"sNaPpY";
 
 // An enumeration describing each of the 4 main chunk types.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -25,6 +45,317 @@ impl TryFrom<u8> for FrameType {
 }
 
 pub fn crc32c_masked(buf: &[u8]) -> u32 {
-  let sum = crc32::checksum_castagnoli(buf);
+  let sum = crc32c(buf);
   (sum.wrapping_shr(15) | sum.wrapping_shl(17)).wrapping_add(0xa282ead8)
 }
+
+// header: type(1), len_le(3), crc_le(4)
+pub fn encode_header(out: &mut BytesMut, chunk_type: FrameType, length: usize, crc: u32) {
+  out.put_uint_le(chunk_type as u8 as u64, 1);
+  out.put_uint_le(length as u64, 3);
+  out.put_u32_le(crc);
+}
+
+// compress one block (at most MAX_BLOCK_SIZE) into a frame, appended to
+// `out`. `scratch` must be at least `codec.max_compress_len(data.len())`.
+// if the compressed size is at least `ratio_cutoff` of the original, the
+// block is stored uncompressed instead. without `checksum`, the CRC is
+// written as zero.
+pub fn encode_frame<C: BlockCodec>(
+  codec: &mut C,
+  scratch: &mut [u8],
+  data: &[u8],
+  ratio_cutoff: f64,
+  checksum: bool,
+  out: &mut BytesMut
+) -> Result<(), io::Error> {
+  let crc = if checksum { crc32c_masked(data) } else { 0 };
+  // this can't really fail, but roll with it:
+  let length = codec.compress(data, scratch)?;
+
+  if length as f64 >= data.len() as f64 * ratio_cutoff {
+    out.reserve(data.len() + 8);
+    encode_header(out, FrameType::Uncompressed, data.len() + 4, crc);
+    out.put(data);
+  } else {
+    out.reserve(length + 8);
+    encode_header(out, FrameType::Compressed, length + 4, crc);
+    out.put(&scratch[..length]);
+  }
+  Ok(())
+}
+
+// parse a 4-byte frame header into (type, body length).
+pub fn decode_header(header: &[u8]) -> (Result<FrameType, u8>, usize) {
+  let mut buf = header.into_buf();
+  let frame_type = FrameType::try_from(buf.get_u8());
+  let length = buf.get_uint_le(3) as usize;
+  (frame_type, length)
+}
+
+// frame types 0x02 - 0x7f are reserved, and must not be skipped.
+fn is_reserved_unskippable(b: u8) -> bool {
+  (0x02..=0x7f).contains(&b)
+}
+
+// check a frame's type and length as soon as the header arrives, before
+// buffering the body. unskippable reserved types are never ok, and data
+// frames always need room for a CRC. in strict mode, also hold them to the
+// spec: no more than one block of uncompressed data, and a 6-byte stream
+// identifier. skippable frames can be any size.
+pub fn check_frame_length<C: BlockCodec>(
+  codec: &C,
+  strict: bool,
+  frame_type: Result<FrameType, u8>,
+  length: usize
+) -> Result<(), io::Error> {
+  match frame_type {
+    Err(b) if is_reserved_unskippable(b) => Err(Error::UnknownFrameType(b).into()),
+    Ok(FrameType::Compressed) | Ok(FrameType::Uncompressed) if length < 4 => {
+      Err(Error::InvalidLength(Container::Framed).into())
+    },
+    _ if !strict => Ok(()),
+    Ok(FrameType::Stream) if length != MAGIC.len() => {
+      Err(Error::MangledMagic(Container::Framed).into())
+    },
+    Ok(FrameType::Uncompressed) if length > codec.max_block_size() + 4 => {
+      Err(Error::BlockTooLarge(Container::Framed).into())
+    },
+    Ok(FrameType::Compressed) if length > codec.max_compress_len(codec.max_block_size()) + 4 => {
+      Err(Error::BlockTooLarge(Container::Framed).into())
+    },
+    _ => Ok(()),
+  }
+}
+
+// given a frame's expected CRC and its uncompressed data, decide whether
+// it's ok.
+pub type CrcCheck<'a> = dyn FnMut(u32, &[u8]) -> Result<(), io::Error> + 'a;
+
+// fail if a frame's data doesn't match its CRC.
+pub fn verify_crc(expected: u32, data: &[u8]) -> Result<(), io::Error> {
+  let actual = crc32c_masked(data);
+  if actual != expected {
+    Err(Error::CrcMismatch { expected, actual }.into())
+  } else {
+    Ok(())
+  }
+}
+
+// validate a frame body and return the uncompressed data, if it has any.
+// `seen_magic` tracks whether the stream identifier has gone by yet. in
+// strict mode, a compressed block can't claim to be bigger than a block.
+// compressed blocks are decoded into `arena`. `check_crc` is given the
+// expected CRC and the uncompressed data, and decides what to do about it
+// (usually `verify_crc`).
+pub fn decode_frame<C: BlockCodec>(
+  codec: &mut C,
+  seen_magic: &mut bool,
+  strict: bool,
+  check_crc: &mut CrcCheck,
+  arena: &mut BytesMut,
+  frame_type: Result<FrameType, u8>,
+  data: Bytes
+) -> Result<Option<Bytes>, io::Error> {
+  // some error cases first: expect to have seen at least one magic header, and a known frame type.
+  if !*seen_magic && frame_type != Ok(FrameType::Stream) {
+    return Err(Error::MissingMagic(Container::Framed).into());
+  }
+
+  match frame_type {
+    Err(b) if is_reserved_unskippable(b) => {
+      Err(Error::UnknownFrameType(b).into())
+    },
+
+    Ok(FrameType::Stream) => {
+      if data.as_ref() != MAGIC {
+        Err(Error::MangledMagic(Container::Framed).into())
+      } else {
+        // skip.
+        *seen_magic = true;
+        Ok(None)
+      }
+    },
+
+    Ok(FrameType::Uncompressed) => {
+      let out = data.slice_from(4);
+      let expected_crc = data.into_buf().get_u32_le();
+      check_crc(expected_crc, out.as_ref())?;
+      Ok(Some(out))
+    },
+
+    Ok(FrameType::Compressed) => {
+      let compressed = data.slice_from(4);
+      let expected_crc = data.into_buf().get_u32_le();
+      if strict && codec.decompress_len(compressed.as_ref())? > codec.max_block_size() {
+        return Err(Error::BlockTooLarge(Container::Framed).into());
+      }
+      let uncompressed = decompress(codec, compressed.as_ref(), arena)?;
+      check_crc(expected_crc, uncompressed.as_ref())?;
+      Ok(Some(uncompressed))
+    },
+
+    // anything else can be skipped:
+    _ => Ok(None)
+  }
+}
+
+// decompress a whole block, carving the space for it out of `arena`.
+pub fn decompress<C: BlockCodec>(codec: &mut C, data: &[u8], arena: &mut BytesMut) -> Result<Bytes, io::Error> {
+  let len = codec.decompress_len(data)?;
+  reserve_arena(arena, len);
+  arena.resize(len, 0);
+  match codec.decompress(data, &mut arena[..]) {
+    Ok(length) => {
+      let out = arena.split_to(length).freeze();
+      // the codec may use less than it asked for. the arena has to be left
+      // empty for the next frame.
+      arena.clear();
+      Ok(out)
+    },
+    Err(e) => {
+      arena.clear();
+      Err(e)
+    }
+  }
+}
+
+// make sure `arena` (which is always empty) has room for `len` bytes,
+// grabbing a new one if it's used up.
+pub fn reserve_arena(arena: &mut BytesMut, len: usize) {
+  if arena.capacity() < len {
+    arena.reserve(cmp::max(len, ARENA_SIZE));
+  }
+}
+
+// buffer incoming data until there's enough to decode. used by all the
+// stream decoders, which usually need a length prefix before they know how
+// much data to wait for.
+pub struct ByteQueue {
+  saved: VecDeque<Bytes>,
+  saved_length: usize,
+}
+
+impl ByteQueue {
+  pub fn new() -> ByteQueue {
+    ByteQueue { saved: VecDeque::new(), saved_length: 0 }
+  }
+
+  pub fn len(&self) -> usize {
+    self.saved_length
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.saved_length == 0
+  }
+
+  pub fn push(&mut self, data: Bytes) {
+    if !data.is_empty() {
+      self.saved_length += data.len();
+      self.saved.push_back(data);
+    }
+  }
+
+  // parse the frame header at the front of the queue, without removing it.
+  // there must be at least 4 bytes.
+  pub fn peek_header(&self) -> (Result<FrameType, u8>, usize) {
+    decode_header(&[ self.get(0), self.get(1), self.get(2), self.get(3) ])
+  }
+
+  // look at a byte without removing it. `index` must be < len().
+  pub fn get(&self, mut index: usize) -> u8 {
+    for b in &self.saved {
+      if index < b.len() { return b[index]; }
+      index -= b.len();
+    }
+    panic!("ByteQueue index out of range");
+  }
+
+  // pop saved buffers until we have the requested amount, then pack them
+  // into a single Bytes (probably with copying, boo).
+  pub fn drain(&mut self, count: usize) -> Bytes {
+    let rv = self.peek(count);
+    self.skip(count);
+    rv
+  }
+
+  // same as `drain`, but leave the data in the queue.
+  pub fn peek(&self, count: usize) -> Bytes {
+    if count == 0 {
+      return Bytes::new();
+    }
+    let first = &self.saved[0];
+    if first.len() >= count {
+      return first.slice(0, count);
+    }
+
+    // unavoidable copy here. we could build a rope out of the segments,
+    // but snappy will want to take slices. just suck it up and copy.
+    let mut rv: Vec<u8> = Vec::with_capacity(count);
+    for b in &self.saved {
+      let n = cmp::min(b.len(), count - rv.len());
+      rv.extend_from_slice(&b[..n]);
+      if rv.len() == count { break; }
+    }
+    Bytes::from(rv)
+  }
+
+  // like `peek`, but starting `offset` bytes in, and if the data spans more
+  // than one buffer, copying it into `arena` instead of a new allocation.
+  // data that spans buffers is always copied, even if they happen to be
+  // adjacent: bytes 0.4 can only rejoin a `BytesMut` it owns outright.
+  pub fn peek_at(&self, offset: usize, count: usize, arena: &mut BytesMut) -> Bytes {
+    if count == 0 {
+      return Bytes::new();
+    }
+    let mut buffers = self.saved.iter();
+    let mut b = buffers.next().unwrap();
+    let mut start = offset;
+    while start >= b.len() {
+      start -= b.len();
+      b = buffers.next().unwrap();
+    }
+    if start + count <= b.len() {
+      return b.slice(start, start + count);
+    }
+
+    reserve_arena(arena, count);
+    arena.extend_from_slice(&b[start..]);
+    for b in buffers {
+      let n = cmp::min(b.len(), count - arena.len());
+      arena.extend_from_slice(&b[..n]);
+      if arena.len() == count { break; }
+    }
+    arena.split_to(count).freeze()
+  }
+
+  // drop data from the front of the queue, without copying it.
+  pub fn skip(&mut self, mut count: usize) {
+    self.saved_length -= count;
+    while count > 0 {
+      let mut b = self.saved.pop_front().unwrap();
+      if b.len() > count {
+        b.advance(count);
+        self.saved.push_front(b);
+        return;
+      }
+      count -= b.len();
+    }
+  }
+}
+
+impl Default for ByteQueue {
+  fn default() -> ByteQueue {
+    ByteQueue::new()
+  }
+}
+
+// snappy's varint: 7 bits at a time, low bits first.
+pub fn encode_varint(out: &mut BytesMut, mut n: u64) {
+  out.reserve(10);
+  while n >= 0x80 {
+    out.put_u8((n as u8) | 0x80);
+    n >>= 7;
+  }
+  out.put_u8(n as u8);
+}
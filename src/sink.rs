@@ -0,0 +1,214 @@
+use aliases::{ByteSink, ByteStream};
+use bytes::{Bytes};
+use futures::{Async, AsyncSink, Poll, Sink, StartSend, Stream};
+use std::collections::VecDeque;
+use std::io;
+
+use compress::{SnappyCompress};
+use uncompress::{SnappyUncompress};
+
+// push-mode adapters: each one feeds a regular stream transform from a
+// queue, and forwards whatever it generates into an inner sink. that way
+// all the framing logic stays in `compress.rs` and `uncompress.rs`.
+
+/// A `ByteStream` that's fed by hand. It's "not ready" whenever it's empty,
+/// until it's closed.
+pub(crate) struct InputQueue {
+  buffers: VecDeque<Bytes>,
+  closed: bool,
+}
+
+impl InputQueue {
+  pub fn new() -> InputQueue {
+    InputQueue { buffers: VecDeque::new(), closed: false }
+  }
+
+  fn push(&mut self, data: Bytes) {
+    self.buffers.push_back(data);
+  }
+
+  fn close(&mut self) {
+    self.closed = true;
+  }
+
+  fn is_empty(&self) -> bool {
+    self.buffers.is_empty()
+  }
+}
+
+impl Stream for InputQueue {
+  type Item = Bytes;
+  type Error = io::Error;
+
+  fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+    match self.buffers.pop_front() {
+      Some(data) => Ok(Async::Ready(Some(data))),
+      None => {
+        if self.closed { Ok(Async::Ready(None)) } else { Ok(Async::NotReady) }
+      }
+    }
+  }
+}
+
+// shared driver: move generated buffers from `stream` into `sink`, one at a
+// time, until the stream needs more input, finishes, or the sink is full.
+// returns NotReady only if the sink is applying backpressure.
+fn drive<S, K>(stream: &mut S, sink: &mut K, output: &mut Option<Bytes>, done: &mut bool) -> Poll<(), io::Error>
+  where S: ByteStream, K: ByteSink
+{
+  loop {
+    if let Some(data) = output.take() {
+      if let AsyncSink::NotReady(data) = sink.start_send(data)? {
+        *output = Some(data);
+        return Ok(Async::NotReady);
+      }
+    }
+
+    if *done {
+      return Ok(Async::Ready(()));
+    }
+
+    match stream.poll()? {
+      Async::Ready(Some(data)) => *output = Some(data),
+      Async::Ready(None) => {
+        *done = true;
+        return Ok(Async::Ready(()));
+      },
+      Async::NotReady => return Ok(Async::Ready(())),
+    }
+  }
+}
+
+/// Push-mode version of `SnappyCompress`: a `Sink` for uncompressed data,
+/// which writes snappy-framed data into another `Sink`.
+///
+/// `poll_complete` forces out any data buffered by coalescing, so it's also
+/// the way to flush a partial block.
+pub struct SnappyCompressSink<K> where K: ByteSink {
+  compress: SnappyCompress<InputQueue>,
+  sink: K,
+
+  // a frame the inner sink wasn't ready for yet
+  output: Option<Bytes>,
+  done: bool,
+}
+
+impl<K> SnappyCompressSink<K> where K: ByteSink {
+  pub fn new(sink: K) -> SnappyCompressSink<K> {
+    SnappyCompressSink::from_stream(SnappyCompress::new(InputQueue::new()), sink)
+  }
+
+  pub(crate) fn from_stream(compress: SnappyCompress<InputQueue>, sink: K) -> SnappyCompressSink<K> {
+    SnappyCompressSink { compress, sink, output: None, done: false }
+  }
+
+  pub fn get_ref(&self) -> &K {
+    &self.sink
+  }
+
+  pub fn get_mut(&mut self) -> &mut K {
+    &mut self.sink
+  }
+
+  pub fn into_inner(self) -> K {
+    self.sink
+  }
+
+  fn drive(&mut self) -> Poll<(), io::Error> {
+    drive(&mut self.compress, &mut self.sink, &mut self.output, &mut self.done)
+  }
+}
+
+impl<K> Sink for SnappyCompressSink<K> where K: ByteSink {
+  type SinkItem = Bytes;
+  type SinkError = io::Error;
+
+  fn start_send(&mut self, item: Bytes) -> StartSend<Bytes, io::Error> {
+    // only take on new data once everything before it has been handed off.
+    self.drive()?;
+    if self.output.is_some() || !self.compress.get_ref().is_empty() {
+      return Ok(AsyncSink::NotReady(item));
+    }
+    self.compress.get_mut().push(item);
+    self.drive()?;
+    Ok(AsyncSink::Ready)
+  }
+
+  fn poll_complete(&mut self) -> Poll<(), io::Error> {
+    self.compress.flush();
+    try_ready!(self.drive());
+    self.sink.poll_complete()
+  }
+
+  fn close(&mut self) -> Poll<(), io::Error> {
+    self.compress.get_mut().close();
+    try_ready!(self.drive());
+    self.sink.close()
+  }
+}
+
+/// Push-mode version of `SnappyUncompress`: a `Sink` for snappy-framed data,
+/// which writes the uncompressed data into another `Sink`.
+///
+/// Closing the sink checks for a truncated final frame.
+pub struct SnappyUncompressSink<K> where K: ByteSink {
+  uncompress: SnappyUncompress<InputQueue>,
+  sink: K,
+
+  // a buffer the inner sink wasn't ready for yet
+  output: Option<Bytes>,
+  done: bool,
+}
+
+impl<K> SnappyUncompressSink<K> where K: ByteSink {
+  pub fn new(sink: K) -> SnappyUncompressSink<K> {
+    SnappyUncompressSink {
+      uncompress: SnappyUncompress::new(InputQueue::new()),
+      sink,
+      output: None,
+      done: false,
+    }
+  }
+
+  pub fn get_ref(&self) -> &K {
+    &self.sink
+  }
+
+  pub fn get_mut(&mut self) -> &mut K {
+    &mut self.sink
+  }
+
+  pub fn into_inner(self) -> K {
+    self.sink
+  }
+
+  fn drive(&mut self) -> Poll<(), io::Error> {
+    drive(&mut self.uncompress, &mut self.sink, &mut self.output, &mut self.done)
+  }
+}
+
+impl<K> Sink for SnappyUncompressSink<K> where K: ByteSink {
+  type SinkItem = Bytes;
+  type SinkError = io::Error;
+
+  fn start_send(&mut self, item: Bytes) -> StartSend<Bytes, io::Error> {
+    self.drive()?;
+    if self.output.is_some() || !self.uncompress.get_ref().is_empty() {
+      return Ok(AsyncSink::NotReady(item));
+    }
+    self.uncompress.get_mut().push(item);
+    self.drive()?;
+    Ok(AsyncSink::Ready)
+  }
+
+  fn poll_complete(&mut self) -> Poll<(), io::Error> {
+    try_ready!(self.drive());
+    self.sink.poll_complete()
+  }
+
+  fn close(&mut self) -> Poll<(), io::Error> {
+    self.uncompress.get_mut().close();
+    try_ready!(self.drive());
+    self.sink.close()
+  }
+}
@@ -0,0 +1,67 @@
+use futures::{Poll};
+use std::io;
+use std::io::{Read, Write};
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use blocking::{SnappyReader, SnappyWriter};
+
+// the blocking adapters keep all their state across `WouldBlock` errors,
+// so they're also valid tokio adapters when the inner object is.
+
+impl<R> AsyncRead for SnappyReader<R> where R: AsyncRead {}
+
+impl<W> AsyncWrite for SnappyWriter<W> where W: AsyncWrite {
+  fn shutdown(&mut self) -> Poll<(), io::Error> {
+    try_nb!(self.flush());
+    self.get_mut().shutdown()
+  }
+}
+
+// to frame both directions of a socket, each adapter passes the other
+// direction through untouched.
+
+impl<W> Read for SnappyWriter<W> where W: Read + Write {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    self.get_mut().read(buf)
+  }
+}
+
+impl<W> AsyncRead for SnappyWriter<W> where W: AsyncRead + AsyncWrite {}
+
+impl<R> Write for SnappyReader<R> where R: Read + Write {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.get_mut().write(buf)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.get_mut().flush()
+  }
+}
+
+impl<R> AsyncWrite for SnappyReader<R> where R: AsyncRead + AsyncWrite {
+  fn shutdown(&mut self) -> Poll<(), io::Error> {
+    self.get_mut().shutdown()
+  }
+}
+
+/// A snappy-framed duplex connection: everything written is compressed
+/// into frames, and everything read is un-framed. Wrap a `TcpStream` (or
+/// anything that's `AsyncRead + AsyncWrite`) with `SnappyTransport::wrap`.
+///
+/// Writes are buffered into blocks, so `flush` after each message on an
+/// interactive protocol. `shutdown` flushes the final frame.
+pub type SnappyTransport<T> = SnappyReader<SnappyWriter<T>>;
+
+impl<T> SnappyReader<SnappyWriter<T>> where T: Read + Write {
+  pub fn wrap(io: T) -> SnappyTransport<T> {
+    SnappyReader::new(SnappyWriter::new(io))
+  }
+
+  pub fn transport_ref(&self) -> &T {
+    self.get_ref().get_ref()
+  }
+
+  pub fn transport_mut(&mut self) -> &mut T {
+    self.get_mut().get_mut()
+  }
+}
@@ -0,0 +1,227 @@
+use aliases::{ByteStream};
+use bytes::{Buf, BufMut, Bytes, BytesMut, IntoBuf};
+use futures::{Async, Poll, Stream};
+use snap;
+use std::io;
+
+use error::{Container, Error};
+use shared::{ByteQueue};
+
+// snappy-java's default block size.
+pub const XERIAL_BLOCK_SIZE: usize = 32 * 1024;
+
+// stream header: magic(8), version_be(4), compatible_version_be(4)
+pub const XERIAL_MAGIC: &[u8] = b"\x82SNAPPY\x00";
+const XERIAL_HEADER: &[u8] = b"\x82SNAPPY\x00\x00\x00\x00\x01\x00\x00\x00\x01";
+const XERIAL_VERSION: u32 = 1;
+
+/// Compress a stream into the xerial/snappy-java stream format: a 16-byte
+/// header, then a series of raw snappy blocks, each preceded by its
+/// compressed length as a big-endian u32.
+///
+/// Like `SnappyCompress`, each incoming buffer becomes one block, unless
+/// it's too big and has to be split.
+pub struct XerialSnappyCompress<S> where S: ByteStream {
+  stream: S,
+  encoder: snap::Encoder,
+  block_size: usize,
+
+  // the remainder of a buffer that was too big for one block.
+  current_buffer: Option<Bytes>,
+
+  output_buffer: Vec<u8>,
+
+  // the stream has to start with a header
+  sent_header: bool,
+
+  // the input stream has ended, so it mustn't be polled again.
+  done: bool,
+}
+
+impl<S> XerialSnappyCompress<S> where S: ByteStream {
+  pub fn new(stream: S) -> XerialSnappyCompress<S> {
+    XerialSnappyCompress::with_block_size(stream, XERIAL_BLOCK_SIZE)
+  }
+
+  pub fn with_block_size(stream: S, block_size: usize) -> XerialSnappyCompress<S> {
+    assert!(block_size > 0, "block_size must be positive");
+    let output_size = snap::max_compress_len(block_size);
+    let mut s = XerialSnappyCompress {
+      stream,
+      encoder: snap::Encoder::new(),
+      block_size,
+      current_buffer: None,
+      output_buffer: Vec::with_capacity(output_size),
+      sent_header: false,
+      done: false,
+    };
+    // fill the output buffer with zeros for safety.
+    s.output_buffer.resize(output_size, 0);
+    s
+  }
+
+  fn encode_block(&mut self, data: Bytes) -> Result<Bytes, io::Error> {
+    let length = self.encoder.compress(data.as_ref(), &mut self.output_buffer[..])
+      .map_err(Error::Snappy)?;
+    let mut out = BytesMut::with_capacity(length + 4);
+    out.put_u32_be(length as u32);
+    out.put(&self.output_buffer[..length]);
+    Ok(out.freeze())
+  }
+}
+
+impl<S> Stream for XerialSnappyCompress<S> where S: ByteStream {
+  type Item = Bytes;
+  type Error = io::Error;
+
+  fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+    if !self.sent_header {
+      self.sent_header = true;
+      return Ok(Async::Ready(Some(Bytes::from_static(XERIAL_HEADER))));
+    }
+
+    if self.current_buffer.is_none() {
+      if self.done {
+        return Ok(Async::Ready(None));
+      }
+      match self.stream.poll() {
+        Ok(Async::Ready(Some(data))) => {
+          self.current_buffer = Some(data);
+        },
+        Ok(Async::Ready(None)) => {
+          self.done = true;
+          return Ok(Async::Ready(None));
+        },
+        other => {
+          return other;
+        }
+      }
+    }
+
+    let mut buffer = self.current_buffer.take().unwrap();
+    if buffer.len() > self.block_size {
+      self.current_buffer = Some(buffer.split_off(self.block_size));
+    }
+    self.encode_block(buffer).map(|b| Async::Ready(Some(b)))
+  }
+}
+
+#[derive(PartialEq)]
+enum State {
+  // at a block boundary: expecting a block length, or a stream header
+  Boundary,
+
+  // reading a block
+  Body { length: usize },
+}
+
+/// Uncompress a xerial/snappy-java stream. Each block becomes one buffer in
+/// the output stream. Concatenated streams (where a new header shows up
+/// between blocks) are handled too.
+pub struct XerialSnappyUncompress<S> where S: ByteStream {
+  stream: S,
+  decoder: snap::Decoder,
+  state: State,
+
+  // buffer incoming data until we have a full block
+  saved: ByteQueue,
+
+  // the stream has to start with a header
+  seen_header: bool,
+}
+
+impl<S> XerialSnappyUncompress<S> where S: ByteStream {
+  pub fn new(stream: S) -> XerialSnappyUncompress<S> {
+    XerialSnappyUncompress {
+      stream,
+      decoder: snap::Decoder::new(),
+      state: State::Boundary,
+      saved: ByteQueue::new(),
+      seen_header: false,
+    }
+  }
+
+  fn feed(&mut self) -> Option<Poll<Option<Bytes>, io::Error>> {
+    match self.stream.poll() {
+      Ok(Async::Ready(None)) => {
+        if self.state == State::Boundary && self.saved.is_empty() {
+          Some(Ok(Async::Ready(None)))
+        } else {
+          Some(Err(Error::Truncated(Container::Xerial).into()))
+        }
+      },
+      Ok(Async::Ready(Some(data))) => {
+        self.saved.push(data);
+        None
+      }
+      other => Some(other)
+    }
+  }
+
+  // at a block boundary, read either a stream header or a block length.
+  // a block length can't start with 0x82 (it would be negative in java),
+  // so that byte always means a header.
+  fn read_boundary(&mut self) -> Result<Option<State>, io::Error> {
+    if self.saved.is_empty() {
+      return Ok(None);
+    }
+    if self.saved.get(0) == XERIAL_MAGIC[0] {
+      if self.saved.len() < XERIAL_HEADER.len() {
+        return Ok(None);
+      }
+      let header = self.saved.drain(XERIAL_HEADER.len());
+      if &header[..XERIAL_MAGIC.len()] != XERIAL_MAGIC {
+        return Err(Error::MangledMagic(Container::Xerial).into());
+      }
+      let compatible_version = header.slice_from(12).into_buf().get_u32_be();
+      if compatible_version > XERIAL_VERSION {
+        return Err(Error::UnsupportedVersion(Container::Xerial, compatible_version).into());
+      }
+      self.seen_header = true;
+      return Ok(Some(State::Boundary));
+    }
+
+    if !self.seen_header {
+      return Err(Error::MissingMagic(Container::Xerial).into());
+    }
+    if self.saved.len() < 4 {
+      return Ok(None);
+    }
+    let length = self.saved.drain(4).into_buf().get_u32_be() as usize;
+    Ok(Some(State::Body { length }))
+  }
+}
+
+impl<S> Stream for XerialSnappyUncompress<S> where S: ByteStream {
+  type Item = Bytes;
+  type Error = io::Error;
+
+  fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+    loop {
+      let progress = match self.state {
+        State::Boundary => self.read_boundary()?,
+        State::Body { length } => {
+          if self.saved.len() < length {
+            None
+          } else {
+            let compressed = self.saved.drain(length);
+            self.state = State::Boundary;
+            return match self.decoder.decompress_vec(compressed.as_ref()) {
+              Err(e) => Err(Error::Snappy(e).into()),
+              Ok(uncompressed) => Ok(Async::Ready(Some(Bytes::from(uncompressed)))),
+            };
+          }
+        }
+      };
+
+      match progress {
+        Some(state) => self.state = state,
+        None => {
+          if let Some(rv) = self.feed() {
+            return rv;
+          }
+        }
+      }
+    }
+  }
+}
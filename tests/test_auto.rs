@@ -0,0 +1,158 @@
+extern crate bytes;
+extern crate futures;
+extern crate gingersnap;
+
+#[cfg(test)]
+mod test_auto {
+  use bytes::{Bytes};
+  use futures::{Async, Future, Stream, stream};
+  use gingersnap::{
+    BlockDelimiter, ByteStream, HadoopSnappyCompress, SnappyAutoUncompress, SnappyCompress, SnappyFormat,
+    SnappyRawCompress, XerialSnappyCompress
+  };
+  use std::io;
+  use std::vec;
+
+  #[test]
+  fn framed() {
+    let su = SnappyAutoUncompress::new(SnappyCompress::new(sample()));
+    assert_eq!(roundtrip(su), (Some(SnappyFormat::Framed), sample_data()));
+  }
+
+  #[test]
+  fn xerial() {
+    let su = SnappyAutoUncompress::new(XerialSnappyCompress::new(sample()));
+    assert_eq!(roundtrip(su), (Some(SnappyFormat::Xerial), sample_data()));
+  }
+
+  #[test]
+  fn hadoop() {
+    let su = SnappyAutoUncompress::new(HadoopSnappyCompress::new(sample()));
+    assert_eq!(roundtrip(su), (Some(SnappyFormat::Hadoop), sample_data()));
+  }
+
+  #[test]
+  fn hadoop_small() {
+    let s = from_hexes(vec![ "00000005", "00000007", "051068656c6c6f" ]);
+    let su = SnappyAutoUncompress::new(s);
+    assert_eq!(to_hex(su), "68656c6c6f");
+  }
+
+  #[test]
+  fn raw() {
+    let s = from_hexes(vec![ "0510", "68656c6c6f" ]);
+    let mut su = SnappyAutoUncompress::new(s);
+    assert_eq!(su.format(), None);
+    assert_eq!(su.poll().unwrap(), Async::Ready(Some(Bytes::from(&b"hello"[..]))));
+    assert_eq!(su.format(), Some(SnappyFormat::Raw));
+  }
+
+  #[test]
+  fn raw_delimited() {
+    let compressed = SnappyRawCompress::new(sample(), BlockDelimiter::Varint);
+    let su = SnappyAutoUncompress::new(compressed).raw_delimiter(BlockDelimiter::Varint);
+    assert_eq!(roundtrip(su), (Some(SnappyFormat::Raw), sample_data()));
+  }
+
+  #[test]
+  fn raw_with_passthrough() {
+    let compressed = SnappyRawCompress::new(sample(), BlockDelimiter::U32BigEndian);
+    let su = SnappyAutoUncompress::new(compressed).raw_delimiter(BlockDelimiter::U32BigEndian).passthrough(true);
+    assert_eq!(roundtrip(su), (Some(SnappyFormat::Raw), sample_data()));
+  }
+
+  #[test]
+  fn passthrough() {
+    let su = SnappyAutoUncompress::new(sample()).passthrough(true);
+    assert_eq!(roundtrip(su), (Some(SnappyFormat::Uncompressed), sample_data()));
+
+    let s = from_hexes(vec![ "68656c", "6c6f" ]);
+    let su = SnappyAutoUncompress::new(s).passthrough(true);
+    assert_eq!(to_hex(su), "68656c6c6f");
+  }
+
+  #[test]
+  fn not_compressed() {
+    let su = SnappyAutoUncompress::new(sample());
+    let e = su.collect().wait().unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+  }
+
+  #[test]
+  fn never_polls_finished_stream() {
+    // both end the input while detecting.
+    let su = SnappyAutoUncompress::new(PanicAfterEnd::new(b"\x05\x10hello"));
+    assert_eq!(roundtrip(su), (Some(SnappyFormat::Raw), b"hello".to_vec()));
+    let su = SnappyAutoUncompress::new(PanicAfterEnd::new(b"plain old text")).passthrough(true);
+    assert_eq!(roundtrip(su), (Some(SnappyFormat::Uncompressed), b"plain old text".to_vec()));
+  }
+
+  #[test]
+  fn empty() {
+    let s = from_hexes(vec![]);
+    let su = SnappyAutoUncompress::new(s).passthrough(true);
+    assert_eq!(to_hex(su), "");
+  }
+
+
+  fn sample_data() -> Vec<u8> {
+    (0..200000).map(|i| ((i * 7) % 251) as u8).collect()
+  }
+
+  fn sample() -> stream::IterOk<vec::IntoIter<Bytes>, io::Error> {
+    stream::iter_ok(sample_data().chunks(50000).map(Bytes::from).collect::<Vec<Bytes>>())
+  }
+
+  // yields one buffer, then ends, then panics if it's polled again.
+  pub struct PanicAfterEnd {
+    data: Option<Bytes>,
+    done: bool,
+  }
+
+  impl PanicAfterEnd {
+    pub fn new(data: &[u8]) -> PanicAfterEnd {
+      PanicAfterEnd { data: Some(Bytes::from(data)), done: false }
+    }
+  }
+
+  impl Stream for PanicAfterEnd {
+    type Item = Bytes;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Result<Async<Option<Bytes>>, io::Error> {
+      assert!(!self.done, "polled after completion");
+      let data = self.data.take();
+      self.done = data.is_none();
+      Ok(Async::Ready(data))
+    }
+  }
+
+  fn roundtrip<S: ByteStream>(mut s: SnappyAutoUncompress<S>) -> (Option<SnappyFormat>, Vec<u8>) {
+    let mut data: Vec<u8> = Vec::new();
+    loop {
+      match s.poll().unwrap() {
+        Async::Ready(Some(buffer)) => data.extend_from_slice(buffer.as_ref()),
+        Async::Ready(None) => return (s.format(), data),
+        Async::NotReady => panic!("stream not ready"),
+      }
+    }
+  }
+
+  fn to_hex<S: ByteStream>(s: S) -> String {
+    let buffers: Vec<Bytes> = s.collect().wait().unwrap();
+    let strings: Vec<String> = buffers.iter().map(|buffer| {
+      buffer.as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+    }).collect();
+    strings.join("")
+  }
+
+  fn from_hexes(vec: Vec<&str>) -> stream::IterOk<vec::IntoIter<Bytes>, io::Error> {
+    let bytes_vec: Vec<Bytes> = vec.iter().map(|s| {
+      let bytes: Vec<u8> = (0 .. s.len() / 2).map(|i| {
+        u8::from_str_radix(&s[i * 2 .. (i + 1) * 2], 16).unwrap()
+      }).collect();
+      Bytes::from(bytes)
+    }).collect();
+    stream::iter_ok(bytes_vec)
+  }
+}
@@ -0,0 +1,159 @@
+extern crate bytes;
+extern crate futures;
+extern crate gingersnap;
+
+#[cfg(test)]
+mod test_block {
+  use bytes::{Bytes};
+  use futures::{Future, Stream, stream};
+  use gingersnap::{BlockCodec, ByteStream, SnappyCompress, SnappyCompressBuilder, SnappyUncompress};
+  use std::cell::Cell;
+  use std::io;
+  use std::rc::Rc;
+
+  // run-length encoding, as (count, byte) pairs, with tiny blocks.
+  struct RleCodec {
+    blocks: Rc<Cell<usize>>,
+  }
+
+  impl BlockCodec for RleCodec {
+    fn max_block_size(&self) -> usize {
+      16
+    }
+
+    fn max_compress_len(&self, input_len: usize) -> usize {
+      input_len * 2
+    }
+
+    fn compress(&mut self, input: &[u8], output: &mut [u8]) -> Result<usize, io::Error> {
+      self.blocks.set(self.blocks.get() + 1);
+      let mut n = 0;
+      let mut i = 0;
+      while i < input.len() {
+        let mut count = 1;
+        while i + count < input.len() && input[i + count] == input[i] { count += 1; }
+        output[n] = count as u8;
+        output[n + 1] = input[i];
+        n += 2;
+        i += count;
+      }
+      Ok(n)
+    }
+
+    fn decompress_len(&self, input: &[u8]) -> Result<usize, io::Error> {
+      if input.len() & 1 != 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "odd rle"));
+      }
+      Ok(input.chunks(2).map(|pair| pair[0] as usize).sum())
+    }
+
+    fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<usize, io::Error> {
+      let mut n = 0;
+      for pair in input.chunks(2) {
+        for _ in 0..pair[0] {
+          output[n] = pair[1];
+          n += 1;
+        }
+      }
+      Ok(n)
+    }
+  }
+
+  // claims every block is bigger than it is.
+  struct ShortCodec(RleCodec);
+
+  impl BlockCodec for ShortCodec {
+    fn max_block_size(&self) -> usize {
+      self.0.max_block_size()
+    }
+
+    fn max_compress_len(&self, input_len: usize) -> usize {
+      self.0.max_compress_len(input_len)
+    }
+
+    fn compress(&mut self, input: &[u8], output: &mut [u8]) -> Result<usize, io::Error> {
+      self.0.compress(input, output)
+    }
+
+    fn decompress_len(&self, input: &[u8]) -> Result<usize, io::Error> {
+      self.0.decompress_len(input).map(|n| n + 4)
+    }
+
+    fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> Result<usize, io::Error> {
+      self.0.decompress(input, output)
+    }
+  }
+
+  #[test]
+  fn compress() {
+    let blocks = Rc::new(Cell::new(0));
+    let s = stream::once(Ok(Bytes::from(&b"aaaaaaaaaabbbbbbbbbbcc"[..])));
+    let sc = SnappyCompress::with_codec(s, RleCodec { blocks: blocks.clone() });
+    // first block is 10 a's and 6 b's; the second is the rest.
+    assert_eq!(to_hex(sc), format!(
+      "{}{}{}",
+      "ff060000734e61507059",
+      "000800007a16f8c00a610662",
+      "000800006c5ad71d04620263"
+    ));
+    assert_eq!(blocks.get(), 2);
+  }
+
+  #[test]
+  fn block_size_is_capped_by_codec() {
+    let blocks = Rc::new(Cell::new(0));
+    let s = stream::once(Ok(Bytes::from(vec![7u8; 100])));
+    let sc = SnappyCompressBuilder::new().max_block_size(50).build_with_codec(s, RleCodec { blocks: blocks.clone() });
+    let buffers: Vec<Bytes> = sc.collect().wait().unwrap();
+    // stream identifier, then 7 blocks of at most 16 bytes.
+    assert_eq!(buffers.len(), 8);
+    assert_eq!(blocks.get(), 7);
+  }
+
+  #[test]
+  fn roundtrip() {
+    let data: Vec<u8> = (0..5000).map(|i| (i / 37) as u8).collect();
+    let s = stream::iter_ok::<_, io::Error>(data.chunks(300).map(Bytes::from).collect::<Vec<Bytes>>());
+    let sc = SnappyCompress::with_codec(s, RleCodec { blocks: Rc::new(Cell::new(0)) });
+    let su = SnappyUncompress::with_codec(sc, RleCodec { blocks: Rc::new(Cell::new(0)) });
+    let buffers: Vec<Bytes> = su.collect().wait().unwrap();
+    let roundtrip: Vec<u8> = buffers.iter().flat_map(|b| b.to_vec()).collect();
+    assert_eq!(roundtrip, data);
+  }
+
+  #[test]
+  fn codec_returns_less_than_promised() {
+    // the leftover space in the arena mustn't leak into the next frame,
+    // which is split across buffers so it gets copied into the arena.
+    let s = stream::once(Ok(Bytes::from(&b"aaaaaabbbbbb"[..])));
+    let sc = SnappyCompress::with_codec(s, RleCodec { blocks: Rc::new(Cell::new(0)) });
+    let mut buffers: Vec<Bytes> = sc.collect().wait().unwrap();
+    buffers.push(Bytes::from(&b"\x01\x09\x00\x00\xbb\x1f\x1c\x19he"[..]));
+    buffers.push(Bytes::from(&b"llo"[..]));
+    let s = stream::iter_ok::<_, io::Error>(buffers);
+    let su = SnappyUncompress::with_codec(s, ShortCodec(RleCodec { blocks: Rc::new(Cell::new(0)) }));
+    let buffers: Vec<Bytes> = su.collect().wait().unwrap();
+    assert_eq!(buffers, vec![ Bytes::from(&b"aaaaaabbbbbb"[..]), Bytes::from(&b"hello"[..]) ]);
+  }
+
+  #[test]
+  fn codec_errors() {
+    // a compressed frame with an odd-length body.
+    let s = stream::iter_ok::<_, io::Error>(vec![
+      Bytes::from(&b"\xff\x06\x00\x00sNaPpY"[..]),
+      Bytes::from(&b"\x00\x07\x00\x00\x00\x00\x00\x00\x01\x02\x03"[..]),
+    ]);
+    let su = SnappyUncompress::with_codec(s, RleCodec { blocks: Rc::new(Cell::new(0)) });
+    let e = su.collect().wait().unwrap_err();
+    assert_eq!(e.to_string(), "odd rle");
+  }
+
+
+  fn to_hex<S: ByteStream>(s: S) -> String {
+    let buffers: Vec<Bytes> = s.collect().wait().unwrap();
+    let strings: Vec<String> = buffers.iter().map(|buffer| {
+      buffer.as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+    }).collect();
+    strings.join("")
+  }
+}
@@ -0,0 +1,120 @@
+extern crate gingersnap;
+
+#[cfg(test)]
+mod test_blocking {
+  use gingersnap::{SnappyCompressBuilder, SnappyReader, SnappyWriter};
+  use std::cmp;
+  use std::fs;
+  use std::io;
+  use std::io::{BufRead, Read, Write};
+
+  static HEADER: &str = "ff060000734e61507059";
+
+  #[test]
+  fn write_small_data() {
+    let mut w = SnappyWriter::new(Vec::new());
+    w.write_all(b"hello").unwrap();
+    let out = w.finish().unwrap();
+    assert_eq!(to_hex(&out), format!("{}{}{}{}", HEADER, "01090000", "bb1f1c19", "68656c6c6f"));
+  }
+
+  #[test]
+  fn write_nothing() {
+    let w = SnappyWriter::new(Vec::new());
+    assert_eq!(to_hex(&w.finish().unwrap()), HEADER);
+  }
+
+  #[test]
+  fn write_coalesces_until_flush() {
+    let mut w = SnappyCompressBuilder::new().stream_identifier(false).build_writer(Vec::new());
+    w.write_all(b"hel").unwrap();
+    w.write_all(b"lo").unwrap();
+    assert_eq!(w.get_ref().len(), 0);
+    w.flush().unwrap();
+    w.write_all(b"hello").unwrap();
+    let frame = format!("{}{}{}", "01090000", "bb1f1c19", "68656c6c6f");
+    assert_eq!(to_hex(&w.finish().unwrap()), format!("{}{}", frame, frame));
+  }
+
+  #[test]
+  fn write_flushes_on_drop() {
+    let mut out = Vec::new();
+    {
+      let mut w = SnappyWriter::new(&mut out);
+      w.write_all(b"hello").unwrap();
+    }
+    assert_eq!(to_hex(&out), format!("{}{}{}{}", HEADER, "01090000", "bb1f1c19", "68656c6c6f"));
+  }
+
+  #[test]
+  fn read_compressed() {
+    let input = from_hex(&format!("{}{}{}{}", HEADER, "000a0000", "59772563", "1800395a0100"));
+    let mut out = Vec::new();
+    SnappyReader::new(&input[..]).read_to_end(&mut out).unwrap();
+    assert_eq!(to_hex(&out), "393939393939393939393939393939393939393939393939");
+  }
+
+  #[test]
+  fn read_one_byte_at_a_time() {
+    let input = from_hex(&format!("{}{}{}{}", HEADER, "000a0000", "59772563", "1800395a0100"));
+    let mut out = Vec::new();
+    SnappyReader::new(Trickle { data: &input[..] }).read_to_end(&mut out).unwrap();
+    assert_eq!(to_hex(&out), "393939393939393939393939393939393939393939393939");
+  }
+
+  #[test]
+  #[should_panic(expected="Truncated snappy frame")]
+  fn read_truncated() {
+    let input = from_hex(&format!("{}{}{}{}", HEADER, "000a0000", "59772563", "1800395a"));
+    let mut out = Vec::new();
+    SnappyReader::new(&input[..]).read_to_end(&mut out).unwrap();
+  }
+
+  #[test]
+  #[should_panic(expected="CRC mismatch")]
+  fn read_wrong_crc() {
+    let input = from_hex(&format!("{}{}{}{}", HEADER, "01090000", "ff1f1c19", "68656c6c6f"));
+    let mut out = Vec::new();
+    SnappyReader::new(&input[..]).read_to_end(&mut out).unwrap();
+  }
+
+  #[test]
+  fn roundtrip_lines() {
+    let mut original = String::new();
+    fs::File::open("./data/alice29.txt").unwrap().read_to_string(&mut original).unwrap();
+
+    let mut w = SnappyWriter::new(Vec::new());
+    w.write_all(original.as_bytes()).unwrap();
+    let compressed = w.finish().unwrap();
+    assert!(compressed.len() < original.len());
+
+    let r = SnappyReader::new(&compressed[..]);
+    let lines: Vec<String> = r.lines().map(|line| line.unwrap()).collect();
+    assert_eq!(lines, original.lines().collect::<Vec<&str>>());
+  }
+
+
+  // a reader that only hands out one byte per read.
+  struct Trickle<'a> {
+    data: &'a [u8],
+  }
+
+  impl<'a> Read for Trickle<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+      let n = cmp::min(1, cmp::min(buf.len(), self.data.len()));
+      buf[..n].copy_from_slice(&self.data[..n]);
+      self.data = &self.data[n..];
+      Ok(n)
+    }
+  }
+
+  fn to_hex(buffer: &[u8]) -> String {
+    buffer.iter().map(|b| format!("{:02x}", b)).collect()
+  }
+
+  fn from_hex(s: &str) -> Vec<u8> {
+    (0 .. s.len() / 2).map(|i| {
+      u8::from_str_radix(&s[i * 2 .. (i + 1) * 2], 16).unwrap()
+    }).collect()
+  }
+}
@@ -0,0 +1,94 @@
+extern crate bytes;
+extern crate futures;
+extern crate gingersnap;
+extern crate tokio_codec;
+
+#[cfg(test)]
+mod test_codec {
+  use bytes::{Bytes, BytesMut};
+  use futures::{Future, Sink, Stream};
+  use gingersnap::{SnappyCompressBuilder, SnappyFrameCodec};
+  use std::io::Cursor;
+  use tokio_codec::{Decoder, Encoder, FramedRead, FramedWrite};
+
+  static HEADER: &str = "ff060000734e61507059";
+
+  #[test]
+  fn encode() {
+    let mut codec = SnappyFrameCodec::new();
+    let mut out = BytesMut::new();
+    codec.encode(Bytes::from(&b"hello"[..]), &mut out).unwrap();
+    codec.encode(Bytes::from(&b"999999999999999999999999"[..]), &mut out).unwrap();
+    assert_eq!(to_hex(&out), format!("{}{}{}{}{}{}{}", HEADER,
+      "01090000", "bb1f1c19", "68656c6c6f",
+      "000a0000", "59772563", "1800395a0100"));
+  }
+
+  #[test]
+  fn encode_splits_blocks() {
+    let mut codec = SnappyCompressBuilder::new().max_block_size(5).stream_identifier(false).build_codec();
+    let mut out = BytesMut::new();
+    codec.encode(Bytes::from(&b"hellohello"[..]), &mut out).unwrap();
+    let frame = format!("{}{}{}", "01090000", "bb1f1c19", "68656c6c6f");
+    assert_eq!(to_hex(&out), format!("{}{}", frame, frame));
+  }
+
+  #[test]
+  fn decode_partial() {
+    let mut codec = SnappyFrameCodec::new();
+    let mut input = BytesMut::from(from_hex(&format!("{}{}{}", HEADER, "000a0000", "597725")));
+    assert_eq!(codec.decode(&mut input).unwrap(), None);
+    input.extend_from_slice(&from_hex("631800395a0100"));
+    let data = codec.decode(&mut input).unwrap().unwrap();
+    assert_eq!(to_hex(&data), "393939393939393939393939393939393939393939393939");
+    assert_eq!(input.len(), 0);
+    assert_eq!(codec.decode_eof(&mut input).unwrap(), None);
+  }
+
+  #[test]
+  #[should_panic(expected="Truncated snappy frame")]
+  fn decode_truncated() {
+    let mut codec = SnappyFrameCodec::new();
+    let mut input = BytesMut::from(from_hex(&format!("{}{}{}", HEADER, "000a0000", "597725")));
+    codec.decode_eof(&mut input).unwrap();
+  }
+
+  #[test]
+  #[should_panic(expected="missing magic")]
+  fn decode_missing_magic() {
+    let mut codec = SnappyFrameCodec::new();
+    let mut input = BytesMut::from(from_hex(&format!("{}{}{}", "000a0000", "59772563", "1800395a0100")));
+    codec.decode(&mut input).unwrap();
+  }
+
+  #[test]
+  #[should_panic(expected="Unknown frame type")]
+  fn decode_unknown_frame_type() {
+    let mut codec = SnappyFrameCodec::new();
+    let mut input = BytesMut::from(from_hex(&format!("{}{}{}{}", HEADER, "030a0000", "ff772563", "1800395a0100")));
+    codec.decode(&mut input).unwrap();
+  }
+
+  #[test]
+  fn framed_roundtrip() {
+    let messages: Vec<Bytes> = (0..10).map(|i| Bytes::from(vec![ i as u8; 1000 * i ])).collect();
+    let writer = FramedWrite::new(Cursor::new(Vec::new()), SnappyFrameCodec::new());
+    let writer = writer.send_all(futures::stream::iter_ok::<_, std::io::Error>(messages.clone())).wait().unwrap().0;
+    let compressed = writer.into_inner().into_inner();
+
+    let reader = FramedRead::new(Cursor::new(compressed), SnappyFrameCodec::new());
+    let decoded: Vec<Bytes> = reader.collect().wait().unwrap();
+    assert_eq!(decoded, messages);
+  }
+
+
+  fn to_hex(buffer: &[u8]) -> String {
+    buffer.iter().map(|b| format!("{:02x}", b)).collect()
+  }
+
+  fn from_hex(s: &str) -> Vec<u8> {
+    (0 .. s.len() / 2).map(|i| {
+      u8::from_str_radix(&s[i * 2 .. (i + 1) * 2], 16).unwrap()
+    }).collect()
+  }
+}
@@ -1,12 +1,17 @@
 extern crate bytes;
 extern crate futures;
 extern crate gingersnap;
+extern crate tokio;
 
 #[cfg(test)]
 mod test_compress {
   use bytes::{BufMut, Bytes, BytesMut};
-  use futures::{Future, stream};
-  use gingersnap::{ByteStream, SnappyCompress};
+  use futures::{Async, Future, Sink, Stream, future, stream};
+  use futures::sync::mpsc;
+  use gingersnap::{ByteStream, SnappyCompress, SnappyCompressBuilder};
+  use std::io;
+  use std::time::Duration;
+  use tokio::runtime::Runtime;
 
   static HEADER: &str = "ff060000734e61507059";
 
@@ -37,9 +42,145 @@ mod test_compress {
     assert_eq!(to_hex(sc), format!("{}{}{}{}", HEADER, frame, frame, frame));
   }
 
+  #[test]
+  fn no_stream_identifier() {
+    let s = stream::once(Ok(Bytes::from(&b"hello"[..])));
+    let sc = SnappyCompressBuilder::new().stream_identifier(false).build(s);
+    assert_eq!(to_hex(sc), format!("{}{}{}", "01090000", "bb1f1c19", "68656c6c6f"));
+  }
+
+  #[test]
+  fn ratio_cutoff() {
+    // compresses to 6 bytes out of 24, which a cutoff of 0.2 rejects.
+    let s = stream::once(Ok(Bytes::from(&b"999999999999999999999999"[..])));
+    let sc = SnappyCompressBuilder::new().ratio_cutoff(0.2).build(s);
+    assert_eq!(to_hex(sc), format!("{}{}{}{}", HEADER, "011c0000", "59772563", "393939393939393939393939393939393939393939393939"));
+  }
+
+  #[test]
+  #[should_panic(expected="ratio_cutoff must be")]
+  fn ratio_cutoff_nan() {
+    SnappyCompressBuilder::new().ratio_cutoff(f64::NAN);
+  }
+
+  #[test]
+  fn max_block_size() {
+    let s = stream::once(Ok(Bytes::from(&b"hellohello"[..])));
+    let sc = SnappyCompressBuilder::new().max_block_size(5).build(s);
+    let frame = format!("{}{}{}", "01090000", "bb1f1c19", "68656c6c6f");
+    assert_eq!(to_hex(sc), format!("{}{}{}", HEADER, frame, frame));
+  }
+
+  #[test]
+  fn coalesce_small_chunks() {
+    let b = Bytes::from(vec![0u8; 32]);
+    let s = stream::iter_ok::<_, io::Error>(vec![ b.clone(), b.clone(), b ]);
+    let sc = SnappyCompressBuilder::new().coalesce(true).build(s);
+    assert_eq!(to_hex(sc), format!("{}{}{}{}", HEADER, "000d0000", "bc8ed9ab", "600000fe01007a0100"));
+  }
+
+  #[test]
+  fn coalesce_ignores_chunking() {
+    let data: Vec<u8> = (0..200000).map(|i| ((i * 7) % 251) as u8).collect();
+    let data = Bytes::from(data);
+    let whole = stream::once(Ok(data.clone()));
+    let expected = to_hex(SnappyCompressBuilder::new().coalesce(true).build(whole));
+
+    for &chunk_size in [ 1000, 4096, 65535, 65537, 150000 ].iter() {
+      let chunks: Vec<Bytes> = data.chunks(chunk_size).map(|chunk| data.slice_ref(chunk)).collect();
+      let sc = SnappyCompressBuilder::new().coalesce(true).build(stream::iter_ok::<_, io::Error>(chunks));
+      assert_eq!(to_hex(sc), expected);
+    }
+  }
+
+  #[test]
+  fn coalesce_flush_marker() {
+    let s = stream::iter_ok::<_, io::Error>(vec![ Bytes::from(&b"hello"[..]), Bytes::new(), Bytes::from(&b"hello"[..]) ]);
+    let sc = SnappyCompressBuilder::new().coalesce(true).build(s);
+    let frame = format!("{}{}{}", "01090000", "bb1f1c19", "68656c6c6f");
+    assert_eq!(to_hex(sc), format!("{}{}{}", HEADER, frame, frame));
+  }
+
+  #[test]
+  fn coalesce_flush() {
+    let (tx, rx) = mpsc::unbounded::<Bytes>();
+    let rx = rx.map_err(|_| io::Error::other("oops"));
+    let mut sc = SnappyCompressBuilder::new().coalesce(true).build(rx);
+    tx.unbounded_send(Bytes::from(&b"hello"[..])).unwrap();
+
+    let sc = future::lazy(move || {
+      assert_eq!(sc.poll().unwrap(), Async::Ready(Some(Bytes::from(&b"\xff\x06\x00\x00sNaPpY"[..]))));
+      assert_eq!(sc.poll().unwrap(), Async::NotReady);
+      sc.flush();
+      match sc.poll().unwrap() {
+        Async::Ready(Some(frame)) => assert_eq!(frame.len(), 13),
+        other => panic!("expected a frame, got {:?}", other),
+      }
+      assert_eq!(sc.poll().unwrap(), Async::NotReady);
+      Ok::<_, ()>(())
+    });
+    sc.wait().unwrap();
+    drop(tx);
+  }
+
+  #[test]
+  fn coalesce_max_latency() {
+    let (tx, rx) = mpsc::channel::<Bytes>(1);
+    let rx = rx.map_err(|_| io::Error::other("oops"));
+    let sc = SnappyCompressBuilder::new().coalesce(true).max_latency(Duration::from_millis(10)).build(rx);
+    let tx = tx.send(Bytes::from(&b"hello"[..])).wait().unwrap();
+
+    // the input never ends, so the only way to get the frame is the timer.
+    let mut runtime = Runtime::new().unwrap();
+    let buffers = runtime.block_on(sc.take(2).collect()).unwrap();
+    let frame = format!("{}{}{}", "01090000", "bb1f1c19", "68656c6c6f");
+    assert_eq!(hex(&buffers), format!("{}{}", HEADER, frame));
+    drop(tx);
+  }
+
+  #[test]
+  fn never_polls_finished_stream() {
+    for &coalesce in [ false, true ].iter() {
+      let mut sc = SnappyCompressBuilder::new().coalesce(coalesce).build(PanicAfterEnd::new(b"hello"));
+      let frame = format!("{}{}{}", "01090000", "bb1f1c19", "68656c6c6f");
+      let mut buffers = Vec::new();
+      while let Async::Ready(Some(b)) = sc.poll().unwrap() { buffers.push(b) }
+      assert_eq!(hex(&buffers), format!("{}{}", HEADER, frame));
+      assert_eq!(sc.poll().unwrap(), Async::Ready(None));
+    }
+  }
+
+
+  // yields one buffer, then ends, then panics if it's polled again.
+  pub struct PanicAfterEnd {
+    data: Option<Bytes>,
+    done: bool,
+  }
+
+  impl PanicAfterEnd {
+    pub fn new(data: &[u8]) -> PanicAfterEnd {
+      PanicAfterEnd { data: Some(Bytes::from(data)), done: false }
+    }
+  }
+
+  impl Stream for PanicAfterEnd {
+    type Item = Bytes;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Result<Async<Option<Bytes>>, io::Error> {
+      assert!(!self.done, "polled after completion");
+      let data = self.data.take();
+      self.done = data.is_none();
+      Ok(Async::Ready(data))
+    }
+  }
 
   fn to_hex<S: ByteStream>(s: S) -> String {
     let buffers: Vec<Bytes> = s.collect().wait().unwrap();
+    hex(&buffers)
+  }
+
+  fn hex(buffers: &[Bytes]) -> String {
     let strings: Vec<String> = buffers.iter().map(|buffer| {
       buffer.as_ref().iter().map(|b| format!("{:02x}", b)).collect()
     }).collect();
@@ -0,0 +1,49 @@
+extern crate gingersnap;
+
+#[cfg(test)]
+mod test_crc32c {
+  use gingersnap::crc32c::{crc32c, crc32c_table};
+  use gingersnap::shared::{crc32c_masked};
+
+  #[test]
+  fn known_values() {
+    assert_eq!(crc32c(b""), 0);
+    assert_eq!(crc32c(b"123456789"), 0xe3069283);
+    assert_eq!(crc32c(&[ 0u8; 32 ]), 0x8a9136aa);
+    assert_eq!(crc32c(&[ 0xffu8; 32 ]), 0x62a8ab43);
+    assert_eq!(crc32c_masked(b"hello"), 0x191c1fbb);
+  }
+
+  #[test]
+  fn same_as_table_for_all_sizes_and_alignments() {
+    let data = noise(1100);
+    for offset in 0..16 {
+      for len in 0..1024 {
+        let buf = &data[offset .. offset + len];
+        assert_eq!(crc32c(buf), crc32c_table(buf), "offset {} len {}", offset, len);
+      }
+    }
+  }
+
+  #[test]
+  fn same_as_table_for_large_buffers() {
+    let data = noise(1 << 20);
+    for &len in [ 65535, 65536, 65537, 1 << 20 ].iter() {
+      for offset in 0..8 {
+        let buf = &data[offset .. len];
+        assert_eq!(crc32c(buf), crc32c_table(buf), "offset {} len {}", offset, len);
+      }
+    }
+  }
+
+
+  fn noise(len: usize) -> Vec<u8> {
+    let mut x: u32 = 0x12345678;
+    (0..len).map(|_| {
+      x ^= x << 13;
+      x ^= x >> 17;
+      x ^= x << 5;
+      (x >> 24) as u8
+    }).collect()
+  }
+}
@@ -0,0 +1,103 @@
+extern crate bytes;
+extern crate futures;
+extern crate gingersnap;
+
+#[cfg(test)]
+mod test_error {
+  use bytes::{Bytes};
+  use futures::{Future, Stream, stream};
+  use gingersnap::{Container, Error, Position, SnappyReader, SnappyUncompress, XerialSnappyUncompress};
+  use std::error::Error as StdError;
+  use std::io;
+  use std::io::Read;
+
+  static HEADER: &str = "ff060000734e61507059";
+
+  #[test]
+  fn crc_mismatch() {
+    let e = uncompress(&format!("{}{}{}{}", HEADER, "01090000", "ff1f1c19", "68656c6c6f"));
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    match Error::from_io(&e).map(Error::inner) {
+      Some(&Error::CrcMismatch { expected, actual }) => {
+        assert_eq!(expected, 0x191c1fff);
+        assert_eq!(actual, 0x191c1fbb);
+      },
+      other => panic!("wrong error: {:?}", other),
+    }
+    assert_eq!(e.to_string(), "Frame CRC mismatch: expected 191c1fff, got 191c1fbb (frame 1 at byte 10)");
+  }
+
+  #[test]
+  fn unknown_frame_type() {
+    let e = uncompress(&format!("{}{}", HEADER, "0a0000"));
+    assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+    match Error::from_io(&e).map(Error::inner) {
+      Some(&Error::Truncated(Container::Framed)) => (),
+      other => panic!("wrong error: {:?}", other),
+    }
+
+    let e = uncompress(&format!("{}{}", HEADER, "0a00000000"));
+    match Error::from_io(&e).map(Error::inner) {
+      Some(&Error::UnknownFrameType(10)) => (),
+      other => panic!("wrong error: {:?}", other),
+    }
+  }
+
+  #[test]
+  fn magic() {
+    match Error::from_io(&uncompress("0105000068656c6c6f")).map(Error::inner) {
+      Some(&Error::MissingMagic(Container::Framed)) => (),
+      other => panic!("wrong error: {:?}", other),
+    }
+
+    let s = stream::once(Ok(from_hex("82534e41505059ff0000000100000001")));
+    let e = XerialSnappyUncompress::new(s).collect().wait().unwrap_err();
+    match Error::from_io(&e) {
+      Some(&Error::MangledMagic(Container::Xerial)) => (),
+      other => panic!("wrong error: {:?}", other),
+    }
+  }
+
+  #[test]
+  fn snappy_source() {
+    // valid frame, but the compressed data is nonsense.
+    let e = uncompress(&format!("{}{}{}{}", HEADER, "000a0000", "00000000", "ffffffffffff"));
+    let inner = Error::from_io(&e).unwrap().inner();
+    match *inner {
+      Error::Snappy(_) => (),
+      ref other => panic!("wrong error: {:?}", other),
+    }
+    assert!(inner.source().is_some());
+  }
+
+  #[test]
+  fn position() {
+    let hello = "01090000bb1f1c1968656c6c6f";
+    let e = uncompress(&format!("{}{}{}{}{}", HEADER, hello, hello, "01090000", "ff1f1c1968656c6c6f"));
+    let position = Error::from_io(&e).unwrap().position();
+    assert_eq!(position, Some(Position { offset: 36, frame: 3 }));
+
+    // truncation is reported at the start of the partial frame.
+    let e = uncompress(&format!("{}{}{}", HEADER, hello, "0109000000"));
+    let position = Error::from_io(&e).unwrap().position();
+    assert_eq!(position, Some(Position { offset: 23, frame: 2 }));
+
+    let mut reader = SnappyReader::new(io::Cursor::new(from_hex(&format!("{}{}", HEADER, hello))));
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer).unwrap();
+    assert_eq!(reader.position(), Position { offset: 23, frame: 2 });
+  }
+
+
+  fn uncompress(hex: &str) -> io::Error {
+    let s = stream::once::<Bytes, io::Error>(Ok(from_hex(hex)));
+    SnappyUncompress::new(s).collect().wait().unwrap_err()
+  }
+
+  fn from_hex(s: &str) -> Bytes {
+    let bytes: Vec<u8> = (0 .. s.len() / 2).map(|i| {
+      u8::from_str_radix(&s[i * 2 .. (i + 1) * 2], 16).unwrap()
+    }).collect();
+    Bytes::from(bytes)
+  }
+}
@@ -0,0 +1,307 @@
+extern crate bytes;
+extern crate gingersnap;
+extern crate snap;
+
+#[cfg(test)]
+mod test_frame {
+  use bytes::{Bytes, BytesMut};
+  use gingersnap::{Container, CrcPolicy, DecodeEvent, Error, FrameDecoder, FrameEncoder, Position, SnappyCompressBuilder};
+  use std::sync::{Arc, Mutex};
+
+  static HEADER: &str = "ff060000734e61507059";
+
+  #[test]
+  fn decode_events() {
+    let mut decoder = FrameDecoder::new();
+    decoder.push_slice(&from_hex(&format!("{}{}{}{}{}", HEADER, "fe020000", "0000", "01090000", "bb1f1c19")));
+    assert_eq!(decoder.next_event().unwrap(), Some(DecodeEvent::StreamIdentifier));
+    assert_eq!(decoder.next_event().unwrap(), Some(DecodeEvent::Skipped(0xfe)));
+    assert_eq!(decoder.next_event().unwrap(), None);
+    assert!(decoder.finish().is_err());
+
+    decoder.push_slice(&from_hex("68656c6c6f"));
+    assert_eq!(decoder.next_event().unwrap(), Some(DecodeEvent::Data(Bytes::from(&b"hello"[..]))));
+    assert_eq!(decoder.next_event().unwrap(), None);
+    assert_eq!(decoder.buffered(), 0);
+    decoder.finish().unwrap();
+  }
+
+  #[test]
+  fn decode_byte_at_a_time() {
+    let input = from_hex(&format!("{}{}{}{}", HEADER, "000a0000", "59772563", "1800395a0100"));
+    let mut decoder = FrameDecoder::new();
+    let mut out: Vec<u8> = Vec::new();
+    for b in input.iter() {
+      decoder.push_slice(&[ *b ]);
+      while let Some(event) = decoder.next_event().unwrap() {
+        if let DecodeEvent::Data(data) = event { out.extend_from_slice(&data) }
+      }
+    }
+    decoder.finish().unwrap();
+    assert_eq!(to_hex(&out), "393939393939393939393939393939393939393939393939");
+  }
+
+  #[test]
+  fn decode_from_buffer() {
+    let mut decoder = FrameDecoder::new();
+    let mut input = BytesMut::from(from_hex(&format!("{}{}{}", HEADER, "01090000", "bb1f1c19")));
+    assert_eq!(decoder.next_event_from(&mut input).unwrap(), Some(DecodeEvent::StreamIdentifier));
+    assert_eq!(decoder.next_event_from(&mut input).unwrap(), None);
+    input.extend_from_slice(b"hello");
+    assert_eq!(decoder.next_event_from(&mut input).unwrap(), Some(DecodeEvent::Data(Bytes::from(&b"hello"[..]))));
+    assert_eq!(input.len(), 0);
+  }
+
+  #[test]
+  fn decode_without_copying() {
+    // an uncompressed frame comes back as a slice of the input. (bytes
+    // under 32 are always copied, so use something bigger.)
+    let mut out = BytesMut::new();
+    SnappyCompressBuilder::new().ratio_cutoff(0.0).build_encoder().encode(&[7u8; 100], &mut out).unwrap();
+    let input = out.freeze();
+    let mut decoder = FrameDecoder::new();
+    decoder.push(input.clone());
+    decoder.next_event().unwrap();
+    match decoder.next_event().unwrap() {
+      Some(DecodeEvent::Data(data)) => assert_eq!(data.as_ptr(), input[18..].as_ptr()),
+      other => panic!("wrong event: {:?}", other),
+    }
+
+    // compressed frames are decoded next to each other in the same arena.
+    let mut out = BytesMut::new();
+    let mut encoder = SnappyCompressBuilder::new().max_block_size(1000).build_encoder();
+    encoder.encode(&[7u8; 2000], &mut out).unwrap();
+    let mut decoder = FrameDecoder::new();
+    decoder.push(out.freeze());
+    decoder.next_event().unwrap();
+    let blocks: Vec<Bytes> = (0..2).map(|_| match decoder.next_event().unwrap() {
+      Some(DecodeEvent::Data(data)) => data,
+      other => panic!("wrong event: {:?}", other),
+    }).collect();
+    assert_eq!(blocks[0], Bytes::from(vec![7u8; 1000]));
+    assert_eq!(blocks[1].as_ptr(), blocks[0][1000..].as_ptr());
+  }
+
+  #[test]
+  #[should_panic(expected="CRC mismatch")]
+  fn decode_wrong_crc() {
+    let mut decoder = FrameDecoder::new();
+    decoder.push_slice(&from_hex(&format!("{}{}{}{}", HEADER, "01090000", "ff1f1c19", "68656c6c6f")));
+    loop { decoder.next_event().unwrap(); }
+  }
+
+  #[test]
+  fn crc_policy() {
+    let input = from_hex(&format!("{}{}{}{}", HEADER, "01090000", "ff1f1c19", "68656c6c6f"));
+    let hello = Some(DecodeEvent::Data(Bytes::from(&b"hello"[..])));
+
+    let mut decoder = FrameDecoder::new().crc_policy(CrcPolicy::Skip);
+    decoder.push_slice(&input);
+    decoder.next_event().unwrap();
+    assert_eq!(decoder.next_event().unwrap(), hello);
+    assert_eq!(decoder.crc_mismatches(), 0);
+
+    let mut decoder = FrameDecoder::new().crc_policy(CrcPolicy::Report);
+    decoder.push_slice(&input);
+    decoder.next_event().unwrap();
+    assert_eq!(decoder.next_event().unwrap(), hello);
+    assert_eq!(decoder.crc_mismatches(), 1);
+
+    let reported = Arc::new(Mutex::new(Vec::new()));
+    let r = reported.clone();
+    let mut decoder = FrameDecoder::new().on_crc_mismatch(move |e| r.lock().unwrap().push(e.to_string()));
+    decoder.push_slice(&input);
+    decoder.push_slice(&input[10..]);
+    decoder.next_event().unwrap();
+    assert_eq!(decoder.next_event().unwrap(), hello);
+    assert_eq!(decoder.next_event().unwrap(), hello);
+    assert_eq!(decoder.crc_mismatches(), 2);
+    assert_eq!(decoder.position(), Position { offset: 36, frame: 3 });
+    assert_eq!(*reported.lock().unwrap(), vec![
+      "Frame CRC mismatch: expected 191c1fff, got 191c1fbb (frame 1 at byte 10)".to_string(),
+      "Frame CRC mismatch: expected 191c1fff, got 191c1fbb (frame 2 at byte 23)".to_string(),
+    ]);
+  }
+
+  #[test]
+  fn recover() {
+    let hello = "01090000bb1f1c1968656c6c6f";
+    let bad_crc = "01090000ff1f1c1968656c6c6f";
+    let damage = Arc::new(Mutex::new(Vec::new()));
+    let d = damage.clone();
+    let mut decoder = FrameDecoder::new().on_damage(move |range, e| d.lock().unwrap().push((range, e.to_string())));
+    decoder.push_slice(&from_hex(&format!("{}{}{}{}{}{}", HEADER, hello, bad_crc, hello, "7f7f7f", hello)));
+    decoder.push_slice(&from_hex("0109"));
+
+    let data = DecodeEvent::Data(Bytes::from(&b"hello"[..]));
+    let mut events = Vec::new();
+    while let Some(event) = decoder.next_event().unwrap() { events.push(event) }
+    assert_eq!(events, vec![
+      DecodeEvent::StreamIdentifier,
+      data.clone(),
+      DecodeEvent::Damaged(23 .. 36),
+      data.clone(),
+      DecodeEvent::Damaged(49 .. 52),
+      data.clone(),
+    ]);
+    decoder.finish().unwrap();
+    assert_eq!(decoder.next_event().unwrap(), Some(DecodeEvent::Damaged(65 .. 67)));
+    assert_eq!(decoder.next_event().unwrap(), None);
+    assert_eq!(decoder.position(), Position { offset: 67, frame: 7 });
+    assert_eq!(*damage.lock().unwrap(), vec![
+      (23 .. 36, "Frame CRC mismatch: expected 191c1fff, got 191c1fbb (frame 2 at byte 23)".to_string()),
+      (49 .. 52, "Unknown frame type 127 (frame 4 at byte 49)".to_string()),
+      (65 .. 67, "Truncated snappy frame (frame 6 at byte 65)".to_string()),
+    ]);
+  }
+
+  #[test]
+  fn recover_without_checksums() {
+    // with `CrcPolicy::Skip`, a frame with a zero CRC is good enough to resync on.
+    let hello = "010900000000000068656c6c6f";
+    let mut decoder = FrameDecoder::new().crc_policy(CrcPolicy::Skip).recover(true);
+    decoder.push_slice(&from_hex(&format!("{}{}{}{}", HEADER, hello, "7f7f7f", hello)));
+    let data = DecodeEvent::Data(Bytes::from(&b"hello"[..]));
+    let mut events = Vec::new();
+    while let Some(event) = decoder.next_event().unwrap() { events.push(event) }
+    assert_eq!(events, vec![ DecodeEvent::StreamIdentifier, data.clone(), DecodeEvent::Damaged(23 .. 26), data ]);
+    decoder.finish().unwrap();
+  }
+
+  #[test]
+  fn recover_damaged_stream_identifier() {
+    // data frames with a good CRC are trusted even without an identifier.
+    let hello = "01090000bb1f1c1968656c6c6f";
+    let mut decoder = FrameDecoder::new().recover(true);
+    decoder.push_slice(&from_hex(&format!("{}{}{}", "ffffffffffffffffffff", hello, hello)));
+    let data = DecodeEvent::Data(Bytes::from(&b"hello"[..]));
+    let mut events = Vec::new();
+    while let Some(event) = decoder.next_event().unwrap() { events.push(event) }
+    assert_eq!(events, vec![ DecodeEvent::Damaged(0 .. 10), data.clone(), data ]);
+    decoder.finish().unwrap();
+    assert_eq!(decoder.next_event().unwrap(), None);
+  }
+
+  #[test]
+  fn recover_tail() {
+    // the damage looks like the start of a long frame, so the good frame
+    // after it only turns up once there's no more input.
+    let hello = "01090000bb1f1c1968656c6c6f";
+    let mut decoder = FrameDecoder::new().recover(true);
+    decoder.push_slice(&from_hex(&format!("{}{}{}{}", HEADER, "7f", "01ff0000", hello)));
+    assert_eq!(decoder.next_event().unwrap(), Some(DecodeEvent::StreamIdentifier));
+    assert_eq!(decoder.next_event().unwrap(), None);
+    decoder.finish().unwrap();
+    assert_eq!(decoder.next_event().unwrap(), Some(DecodeEvent::Damaged(10 .. 15)));
+    assert_eq!(decoder.next_event().unwrap(), Some(DecodeEvent::Data(Bytes::from(&b"hello"[..]))));
+    assert_eq!(decoder.next_event().unwrap(), None);
+  }
+
+  #[test]
+  fn recover_needs_stream_identifier() {
+    // a data frame before the stream identifier is damage, even if its CRC
+    // is fine. resync picks up at the next frame after it that checks out.
+    let hello = "01090000bb1f1c1968656c6c6f";
+    let mut decoder = FrameDecoder::new().recover(true);
+    decoder.push_slice(&from_hex(&format!("{}{}{}", hello, HEADER, hello)));
+    assert_eq!(decoder.next_event().unwrap(), Some(DecodeEvent::Damaged(0 .. 13)));
+    assert_eq!(decoder.next_event().unwrap(), Some(DecodeEvent::StreamIdentifier));
+    assert_eq!(decoder.next_event().unwrap(), Some(DecodeEvent::Data(Bytes::from(&b"hello"[..]))));
+  }
+
+  #[test]
+  fn strict_frame_length() {
+    // rejected from the header alone, before any of the body arrives.
+    let mut decoder = FrameDecoder::new();
+    decoder.push_slice(&from_hex(&format!("{}{}", HEADER, "01050001")));
+    assert_eq!(decoder.next_event().unwrap(), Some(DecodeEvent::StreamIdentifier));
+    let e = decoder.next_event().unwrap_err();
+    match Error::from_io(&e).map(Error::inner) {
+      Some(&Error::BlockTooLarge(Container::Framed)) => (),
+      other => panic!("wrong error: {:?}", other),
+    }
+
+    let mut decoder = FrameDecoder::new();
+    let mut input = BytesMut::from(from_hex("ff070000734e6150705959"));
+    assert!(decoder.next_event_from(&mut input).is_err());
+
+    let mut decoder = FrameDecoder::new().strict(false);
+    decoder.push_slice(&from_hex(&format!("{}{}", HEADER, "01050001")));
+    assert_eq!(decoder.next_event().unwrap(), Some(DecodeEvent::StreamIdentifier));
+    assert_eq!(decoder.next_event().unwrap(), None);
+  }
+
+  #[test]
+  fn strict_decompress_len() {
+    // a small frame that claims to uncompress to 100KB.
+    let block = snap::Encoder::new().compress_vec(&vec![0u8; 100000]).unwrap();
+    let length = block.len() + 4;
+    let mut input = from_hex(&format!("{}00{:02x}{:02x}00{}", HEADER, length & 0xff, length >> 8, "00000000"));
+    input.extend_from_slice(&block);
+
+    let mut decoder = FrameDecoder::new();
+    decoder.push_slice(&input);
+    decoder.next_event().unwrap();
+    let e = decoder.next_event().unwrap_err();
+    assert_eq!(e.to_string(), "Snappy frame is too large (frame 1 at byte 10)");
+
+    let mut decoder = FrameDecoder::new().strict(false);
+    decoder.push_slice(&input);
+    decoder.next_event().unwrap();
+    assert!(decoder.next_event().unwrap_err().to_string().contains("CRC mismatch"));
+  }
+
+  #[test]
+  fn encode() {
+    let mut encoder = FrameEncoder::new();
+    let mut out = BytesMut::new();
+    encoder.encode(b"hello", &mut out).unwrap();
+    encoder.encode(b"", &mut out).unwrap();
+    assert_eq!(to_hex(&out), format!("{}{}{}{}{}", HEADER, "01090000", "bb1f1c19", "68656c6c6f", "01040000d8ea82a2"));
+  }
+
+  #[test]
+  fn encode_without_checksums() {
+    let mut encoder = SnappyCompressBuilder::new().checksums(false).build_encoder();
+    let mut out = BytesMut::new();
+    encoder.encode(b"hello", &mut out).unwrap();
+    assert_eq!(to_hex(&out), format!("{}{}{}{}", HEADER, "01090000", "00000000", "68656c6c6f"));
+
+    let mut decoder = FrameDecoder::new().crc_policy(CrcPolicy::Skip);
+    decoder.push(out.freeze());
+    decoder.next_event().unwrap();
+    assert_eq!(decoder.next_event().unwrap(), Some(DecodeEvent::Data(Bytes::from(&b"hello"[..]))));
+  }
+
+  #[test]
+  fn encode_roundtrip() {
+    let data: Vec<u8> = (0..200000).map(|i| ((i * 7) % 251) as u8).collect();
+    let mut encoder = SnappyCompressBuilder::new().max_block_size(10000).build_encoder();
+    let mut out = BytesMut::new();
+    encoder.encode(&data, &mut out).unwrap();
+
+    let mut decoder = FrameDecoder::new();
+    decoder.push(out.freeze());
+    let mut roundtrip: Vec<u8> = Vec::new();
+    let mut frames = 0;
+    while let Some(event) = decoder.next_event().unwrap() {
+      if let DecodeEvent::Data(data) = event {
+        roundtrip.extend_from_slice(&data);
+        frames += 1;
+      }
+    }
+    assert_eq!(frames, 20);
+    assert_eq!(roundtrip, data);
+  }
+
+
+  fn to_hex(buffer: &[u8]) -> String {
+    buffer.iter().map(|b| format!("{:02x}", b)).collect()
+  }
+
+  fn from_hex(s: &str) -> Vec<u8> {
+    (0 .. s.len() / 2).map(|i| {
+      u8::from_str_radix(&s[i * 2 .. (i + 1) * 2], 16).unwrap()
+    }).collect()
+  }
+}
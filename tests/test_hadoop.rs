@@ -0,0 +1,136 @@
+extern crate bytes;
+extern crate futures;
+extern crate gingersnap;
+
+#[cfg(test)]
+mod test_hadoop {
+  use bytes::{Bytes};
+  use futures::{Async, Future, Stream, stream};
+  use gingersnap::{ByteStream, HadoopSnappyCompress, HadoopSnappyUncompress};
+  use std::io;
+  use std::vec;
+
+  #[test]
+  fn compress() {
+    let s = stream::once(Ok(Bytes::from(&b"hello"[..])));
+    let sc = HadoopSnappyCompress::new(s);
+    assert_eq!(to_hex(sc), format!("{}{}{}", "00000005", "00000007", "051068656c6c6f"));
+  }
+
+  #[test]
+  fn compress_splits_blocks() {
+    let s = stream::once(Ok(Bytes::from(&b"hellohello"[..])));
+    let sc = HadoopSnappyCompress::with_block_size(s, 5);
+    let block = format!("{}{}{}", "00000005", "00000007", "051068656c6c6f");
+    assert_eq!(to_hex(sc), format!("{}{}", block, block));
+  }
+
+  #[test]
+  fn compress_never_polls_finished_stream() {
+    let mut sc = HadoopSnappyCompress::new(PanicAfterEnd::new(b"hello"));
+    let mut buffers = Vec::new();
+    while let Async::Ready(Some(b)) = sc.poll().unwrap() { buffers.push(b) }
+    assert_eq!(buffers.len(), 1);
+    assert_eq!(sc.poll().unwrap(), Async::Ready(None));
+  }
+
+  #[test]
+  fn uncompress_multiple_chunks() {
+    // one 10-byte block, in two chunks.
+    let s = from_hexes(vec![ "0000000a", "00000007", "0510", "68656c6c6f", "00000007051068656c6c6f" ]);
+    let su = HadoopSnappyUncompress::new(s);
+    assert_eq!(to_hex(su), "68656c6c6f68656c6c6f");
+  }
+
+  #[test]
+  fn uncompress_empty_block() {
+    let s = from_hexes(vec![ "00000000", "00000005", "00000007051068656c6c6f" ]);
+    let su = HadoopSnappyUncompress::new(s);
+    assert_eq!(to_hex(su), "68656c6c6f");
+  }
+
+  #[test]
+  fn roundtrip() {
+    let data: Vec<u8> = (0..600000).map(|i| ((i * 7) % 251) as u8).collect();
+    let s = stream::iter_ok::<_, io::Error>(data.chunks(250000).map(Bytes::from).collect::<Vec<Bytes>>());
+    let su = HadoopSnappyUncompress::new(HadoopSnappyCompress::new(s));
+    let buffers: Vec<Bytes> = su.collect().wait().unwrap();
+    let roundtrip: Vec<u8> = buffers.iter().flat_map(|b| b.to_vec()).collect();
+    assert_eq!(roundtrip, data);
+  }
+
+  #[test]
+  fn roundtrip_empty_chunk() {
+    let chunks = vec![ Bytes::from(&b"abc"[..]), Bytes::new(), Bytes::from(&b"def"[..]) ];
+    let su = HadoopSnappyUncompress::new(HadoopSnappyCompress::new(stream::iter_ok::<_, io::Error>(chunks)));
+    assert_eq!(to_hex(su), "616263646566");
+  }
+
+  #[test]
+  #[should_panic(expected="Truncated hadoop snappy block")]
+  fn truncated_block() {
+    // the block claims 10 bytes, but only has one 5-byte chunk.
+    let s = from_hexes(vec![ "0000000a", "00000007051068656c6c6f" ]);
+    let su = HadoopSnappyUncompress::new(s);
+    to_hex(su);
+  }
+
+  #[test]
+  #[should_panic(expected="Truncated hadoop snappy block")]
+  fn truncated_chunk() {
+    let s = from_hexes(vec![ "00000005", "0000000705106865" ]);
+    let su = HadoopSnappyUncompress::new(s);
+    to_hex(su);
+  }
+
+  #[test]
+  #[should_panic(expected="larger than its block")]
+  fn oversized_chunk() {
+    let s = from_hexes(vec![ "00000004", "00000007051068656c6c6f" ]);
+    let su = HadoopSnappyUncompress::new(s);
+    to_hex(su);
+  }
+
+
+  // yields one buffer, then ends, then panics if it's polled again.
+  pub struct PanicAfterEnd {
+    data: Option<Bytes>,
+    done: bool,
+  }
+
+  impl PanicAfterEnd {
+    pub fn new(data: &[u8]) -> PanicAfterEnd {
+      PanicAfterEnd { data: Some(Bytes::from(data)), done: false }
+    }
+  }
+
+  impl Stream for PanicAfterEnd {
+    type Item = Bytes;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Result<Async<Option<Bytes>>, io::Error> {
+      assert!(!self.done, "polled after completion");
+      let data = self.data.take();
+      self.done = data.is_none();
+      Ok(Async::Ready(data))
+    }
+  }
+
+  fn to_hex<S: ByteStream>(s: S) -> String {
+    let buffers: Vec<Bytes> = s.collect().wait().unwrap();
+    let strings: Vec<String> = buffers.iter().map(|buffer| {
+      buffer.as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+    }).collect();
+    strings.join("")
+  }
+
+  fn from_hexes(vec: Vec<&str>) -> stream::IterOk<vec::IntoIter<Bytes>, io::Error> {
+    let bytes_vec: Vec<Bytes> = vec.iter().map(|s| {
+      let bytes: Vec<u8> = (0 .. s.len() / 2).map(|i| {
+        u8::from_str_radix(&s[i * 2 .. (i + 1) * 2], 16).unwrap()
+      }).collect();
+      Bytes::from(bytes)
+    }).collect();
+    stream::iter_ok(bytes_vec)
+  }
+}
@@ -0,0 +1,165 @@
+extern crate bytes;
+extern crate futures;
+extern crate gingersnap;
+
+#[cfg(test)]
+mod test_lz4 {
+  use bytes::{Bytes};
+  use futures::{Async, Future, Stream, stream};
+  use gingersnap::{ByteStream, Lz4Compress, Lz4Uncompress};
+  use std::fs;
+  use std::io;
+  use std::io::Read;
+  use std::vec;
+
+  // all from the lz4 command-line tool.
+  static EMPTY: &str = "04224d186440a700000000055dcc02";
+  static HELLO: &str = "04224d186440a70500008068656c6c6f00000000f97700fb";
+  static HELLO_CHECKSUMS: &str =
+    "04224d187440bd100000006f68656c6c6f200600055068656c6c6f7220b52800000000bd9cd674";
+
+  #[test]
+  fn compress() {
+    let s = stream::once(Ok(Bytes::from(&b"hello"[..])));
+    assert_eq!(to_hex(Lz4Compress::new(s)), HELLO);
+  }
+
+  #[test]
+  fn compress_empty() {
+    let s = from_hexes(vec![]);
+    assert_eq!(to_hex(Lz4Compress::new(s)), EMPTY);
+  }
+
+  #[test]
+  fn compress_never_polls_finished_stream() {
+    let mut sc = Lz4Compress::new(PanicAfterEnd::new(b"hello"));
+    let mut buffers = Vec::new();
+    while let Async::Ready(Some(b)) = sc.poll().unwrap() { buffers.push(b) }
+    assert_eq!(buffers.len(), 3);
+    assert_eq!(sc.poll().unwrap(), Async::Ready(None));
+  }
+
+  #[test]
+  fn compress_with_block_checksums() {
+    let s = stream::once(Ok(Bytes::from(&b"hello"[..])));
+    let sc = Lz4Compress::new(s).block_checksums(true).content_checksum(false);
+    assert_eq!(to_hex(sc), "04224d187040ad0500008068656c6c6ff97700fb00000000");
+  }
+
+  #[test]
+  fn uncompress() {
+    let s = from_hexes(vec![ &HELLO[0..6], &HELLO[6..20], &HELLO[20..] ]);
+    assert_eq!(to_hex(Lz4Uncompress::new(s)), "68656c6c6f");
+
+    let s = from_hexes(vec![ HELLO_CHECKSUMS ]);
+    let buffers: Vec<Bytes> = Lz4Uncompress::new(s).collect().wait().unwrap();
+    assert_eq!(buffers, vec![ Bytes::from(&b"hello hello hello hello hello hello"[..]) ]);
+  }
+
+  #[test]
+  fn uncompress_concatenated() {
+    let s = from_hexes(vec![ HELLO, EMPTY, "502a4d1803000000010203", HELLO ]);
+    assert_eq!(to_hex(Lz4Uncompress::new(s)), "68656c6c6f68656c6c6f");
+  }
+
+  #[test]
+  fn uncompress_linked_blocks() {
+    let mut original = Vec::new();
+    fs::File::open("./data/alice29.txt").unwrap().read_to_end(&mut original).unwrap();
+    let mut compressed = Vec::new();
+    fs::File::open("./data/alice29.txt.lz4").unwrap().read_to_end(&mut compressed).unwrap();
+
+    let s = stream::iter_ok::<_, io::Error>(compressed.chunks(1000).map(Bytes::from).collect::<Vec<Bytes>>());
+    let buffers: Vec<Bytes> = Lz4Uncompress::new(s).collect().wait().unwrap();
+    let uncompressed: Vec<u8> = buffers.iter().flat_map(|b| b.to_vec()).collect();
+    assert_eq!(uncompressed, original);
+  }
+
+  #[test]
+  fn roundtrip() {
+    let data: Vec<u8> = (0..200000).map(|i| ((i * 7) % 251) as u8).collect();
+    let s = stream::iter_ok::<_, io::Error>(data.chunks(50000).map(Bytes::from).collect::<Vec<Bytes>>());
+    let sc = Lz4Compress::with_block_size(s, 100000).block_checksums(true);
+    let buffers: Vec<Bytes> = Lz4Uncompress::new(sc).collect().wait().unwrap();
+    let roundtrip: Vec<u8> = buffers.iter().flat_map(|b| b.to_vec()).collect();
+    assert_eq!(roundtrip, data);
+  }
+
+  #[test]
+  #[should_panic(expected="missing magic")]
+  fn missing_magic() {
+    let s = from_hexes(vec![ "05000080", "68656c6c6f" ]);
+    to_hex(Lz4Uncompress::new(s));
+  }
+
+  #[test]
+  #[should_panic(expected="header checksum mismatch")]
+  fn bad_header_checksum() {
+    let s = from_hexes(vec![ "04224d186440a8", "0500008068656c6c6f00000000f97700fb" ]);
+    to_hex(Lz4Uncompress::new(s));
+  }
+
+  #[test]
+  #[should_panic(expected="block checksum mismatch")]
+  fn bad_block_checksum() {
+    let s = from_hexes(vec![ "04224d187040ad0500008068656c6c6ff97700fc00000000" ]);
+    to_hex(Lz4Uncompress::new(s));
+  }
+
+  #[test]
+  #[should_panic(expected="content checksum mismatch")]
+  fn bad_content_checksum() {
+    let s = from_hexes(vec![ "04224d186440a70500008068656c6c6f00000000f97700fc" ]);
+    to_hex(Lz4Uncompress::new(s));
+  }
+
+  #[test]
+  #[should_panic(expected="Truncated lz4 frame")]
+  fn truncated() {
+    let s = from_hexes(vec![ &HELLO[..30] ]);
+    to_hex(Lz4Uncompress::new(s));
+  }
+
+
+  // yields one buffer, then ends, then panics if it's polled again.
+  pub struct PanicAfterEnd {
+    data: Option<Bytes>,
+    done: bool,
+  }
+
+  impl PanicAfterEnd {
+    pub fn new(data: &[u8]) -> PanicAfterEnd {
+      PanicAfterEnd { data: Some(Bytes::from(data)), done: false }
+    }
+  }
+
+  impl Stream for PanicAfterEnd {
+    type Item = Bytes;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Result<Async<Option<Bytes>>, io::Error> {
+      assert!(!self.done, "polled after completion");
+      let data = self.data.take();
+      self.done = data.is_none();
+      Ok(Async::Ready(data))
+    }
+  }
+
+  fn to_hex<S: ByteStream>(s: S) -> String {
+    let buffers: Vec<Bytes> = s.collect().wait().unwrap();
+    let strings: Vec<String> = buffers.iter().map(|buffer| {
+      buffer.as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+    }).collect();
+    strings.join("")
+  }
+
+  fn from_hexes(vec: Vec<&str>) -> stream::IterOk<vec::IntoIter<Bytes>, io::Error> {
+    let bytes_vec: Vec<Bytes> = vec.iter().map(|s| {
+      let bytes: Vec<u8> = (0 .. s.len() / 2).map(|i| {
+        u8::from_str_radix(&s[i * 2 .. (i + 1) * 2], 16).unwrap()
+      }).collect();
+      Bytes::from(bytes)
+    }).collect();
+    stream::iter_ok(bytes_vec)
+  }
+}
@@ -0,0 +1,162 @@
+extern crate bytes;
+extern crate futures;
+extern crate gingersnap;
+
+#[cfg(test)]
+mod test_parallel {
+  use bytes::{Bytes};
+  use futures::{Async, Future, Stream, stream};
+  use gingersnap::{
+    Error, ParallelSnappyCompress, ParallelSnappyUncompress, Position, SnappyCompress, SnappyCompressBuilder,
+    SnappyUncompress, WorkerPool
+  };
+  use std::io;
+  use std::sync::Arc;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  static HEADER: &str = "ff060000734e61507059";
+
+  #[test]
+  fn same_as_single_threaded() {
+    let pool = WorkerPool::new(4);
+    let expected = concat(SnappyCompress::new(stream::iter_ok(chunks())));
+    let parallel = concat(ParallelSnappyCompress::new(stream::iter_ok(chunks()), pool.clone()));
+    assert_eq!(parallel, expected);
+
+    let options = SnappyCompressBuilder::new().coalesce(true).max_block_size(10000).ratio_cutoff(0.5);
+    let expected = concat(options.clone().build(stream::iter_ok(chunks())));
+    let parallel = concat(options.build_parallel(stream::iter_ok(chunks()), pool.clone()).max_in_flight(1));
+    assert_eq!(parallel, expected);
+
+    let original: Vec<u8> = chunks().iter().flat_map(|b| b.to_vec()).collect();
+    let s = ParallelSnappyCompress::new(stream::iter_ok(chunks()), pool);
+    assert_eq!(concat(SnappyUncompress::new(s)), original);
+  }
+
+  #[test]
+  fn empty() {
+    let s = stream::iter_ok::<_, io::Error>(Vec::<Bytes>::new());
+    assert_eq!(concat(ParallelSnappyCompress::new(s, WorkerPool::new(2))), b"\xff\x06\x00\x00sNaPpY".to_vec());
+  }
+
+  #[test]
+  fn never_polls_finished_stream() {
+    let options = SnappyCompressBuilder::new().coalesce(true);
+    let sc = options.build_parallel(PanicAfterEnd::new(b"hello"), WorkerPool::new(2));
+    let frame = "01090000bb1f1c1968656c6c6f";
+    assert_eq!(to_hex(&concat(sc)), format!("{}{}", HEADER, frame));
+  }
+
+  #[test]
+  fn bounded_in_flight() {
+    let read = Arc::new(AtomicUsize::new(0));
+    let r = read.clone();
+    let s = stream::iter_ok::<_, io::Error>(vec![ Bytes::from(vec![0u8; 1000]); 100 ]).inspect(move |_| {
+      r.fetch_add(1, Ordering::SeqCst);
+    });
+    let sc = ParallelSnappyCompress::new(s, WorkerPool::new(2)).max_in_flight(3);
+    let (first, rest) = sc.into_future().map_err(|(e, _)| e).wait().unwrap();
+    assert_eq!(first.unwrap().len(), 10);
+    assert_eq!(read.load(Ordering::SeqCst), 0);
+    let (_, rest) = rest.into_future().map_err(|(e, _)| e).wait().unwrap();
+    assert_eq!(read.load(Ordering::SeqCst), 3);
+    assert_eq!(rest.collect().wait().unwrap().len(), 99);
+    assert_eq!(read.load(Ordering::SeqCst), 100);
+  }
+
+  #[test]
+  fn uncompress_same_as_single_threaded() {
+    let pool = WorkerPool::new(4);
+    let original: Vec<u8> = chunks().iter().flat_map(|b| b.to_vec()).collect();
+    let compressed = concat(SnappyCompress::new(stream::iter_ok(chunks())));
+    let input = || stream::iter_ok::<_, io::Error>(compressed.chunks(1000).map(Bytes::from).collect::<Vec<Bytes>>());
+
+    let expected: Vec<Bytes> = SnappyUncompress::new(input()).collect().wait().unwrap();
+    let buffers: Vec<Bytes> = ParallelSnappyUncompress::new(input(), pool.clone()).collect().wait().unwrap();
+    assert_eq!(buffers, expected);
+    assert_eq!(concat(ParallelSnappyUncompress::new(input(), pool).max_in_flight(1)), original);
+  }
+
+  #[test]
+  fn uncompress_first_bad_frame_wins() {
+    let hello = "01090000bb1f1c1968656c6c6f";
+    let bad_crc = "01090000ff1f1c1968656c6c6f";
+    // the bad CRC is found on a worker, after the unknown frame type is
+    // found while splitting, but it comes first in the stream.
+    let s = from_hex(&format!("{}{}{}{}{}", HEADER, hello, bad_crc, hello, "0a00000000"));
+    let mut results = ParallelSnappyUncompress::new(s, WorkerPool::new(2)).wait();
+    assert_eq!(results.next().unwrap().unwrap(), Bytes::from(&b"hello"[..]));
+    let e = results.next().unwrap().unwrap_err();
+    match Error::from_io(&e).map(Error::inner) {
+      Some(&Error::CrcMismatch { .. }) => (),
+      other => panic!("wrong error: {:?}", other),
+    }
+    assert_eq!(Error::from_io(&e).unwrap().position(), Some(Position { offset: 23, frame: 2 }));
+
+    let s = from_hex(&format!("{}{}{}", HEADER, hello, "010900"));
+    let mut results = ParallelSnappyUncompress::new(s, WorkerPool::new(2)).wait();
+    assert_eq!(results.next().unwrap().unwrap(), Bytes::from(&b"hello"[..]));
+    let e = results.next().unwrap().unwrap_err();
+    assert_eq!(e.to_string(), "Truncated snappy frame (frame 2 at byte 23)");
+
+    let s = from_hex(hello);
+    let e = ParallelSnappyUncompress::new(s, WorkerPool::new(1)).collect().wait().unwrap_err();
+    assert_eq!(e.to_string(), "Not a snappy stream (missing magic header) (frame 0 at byte 0)");
+  }
+
+
+  // yields one buffer, then ends, then panics if it's polled again.
+  pub struct PanicAfterEnd {
+    data: Option<Bytes>,
+    done: bool,
+  }
+
+  impl PanicAfterEnd {
+    pub fn new(data: &[u8]) -> PanicAfterEnd {
+      PanicAfterEnd { data: Some(Bytes::from(data)), done: false }
+    }
+  }
+
+  impl Stream for PanicAfterEnd {
+    type Item = Bytes;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Result<Async<Option<Bytes>>, io::Error> {
+      assert!(!self.done, "polled after completion");
+      let data = self.data.take();
+      self.done = data.is_none();
+      Ok(Async::Ready(data))
+    }
+  }
+
+  // chunks of assorted sizes: bigger and smaller than a block, and empty.
+  fn chunks() -> Vec<Bytes> {
+    let data: Vec<u8> = (0..1000000u64).map(|i| ((i * i / 1000) % 251) as u8).collect();
+    let sizes = [ 1, 0, 70000, 65536, 100, 200000, 0, 3, 150000, 12345 ];
+    let mut chunks = Vec::new();
+    let mut offset = 0;
+    for size in sizes.iter().cycle() {
+      if offset >= data.len() { break; }
+      let end = ::std::cmp::min(data.len(), offset + size);
+      chunks.push(Bytes::from(&data[offset..end]));
+      offset = end;
+    }
+    chunks
+  }
+
+  fn from_hex(s: &str) -> stream::Once<Bytes, io::Error> {
+    let bytes: Vec<u8> = (0 .. s.len() / 2).map(|i| {
+      u8::from_str_radix(&s[i * 2 .. (i + 1) * 2], 16).unwrap()
+    }).collect();
+    stream::once(Ok(Bytes::from(bytes)))
+  }
+
+  fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+  }
+
+  fn concat<S: Stream<Item = Bytes, Error = io::Error>>(s: S) -> Vec<u8> {
+    let buffers: Vec<Bytes> = s.collect().wait().unwrap();
+    buffers.iter().flat_map(|b| b.to_vec()).collect()
+  }
+}
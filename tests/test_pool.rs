@@ -0,0 +1,70 @@
+extern crate bytes;
+extern crate futures;
+extern crate gingersnap;
+
+#[cfg(test)]
+mod test_pool {
+  use bytes::{Bytes, BytesMut};
+  use futures::{Future, Stream, stream};
+  use gingersnap::{BufferPool, DecodeEvent, FrameDecoder, SnappyCompressBuilder, SnappyUncompress};
+  use std::io;
+
+  #[test]
+  fn bounded() {
+    let pool = BufferPool::new(2);
+    let buffers: Vec<BytesMut> = (0..3).map(|_| pool.get(100)).collect();
+    assert!(buffers.iter().all(|b| b.capacity() >= 100 && b.is_empty()));
+    assert_eq!(pool.len(), 0);
+    for mut b in buffers {
+      b.extend_from_slice(b"hello");
+      pool.put(b);
+    }
+    assert_eq!(pool.len(), 2);
+    assert!(pool.get(10).is_empty());
+    assert_eq!(pool.len(), 1);
+  }
+
+  #[test]
+  fn shared_by_encoders() {
+    let pool = BufferPool::new(4);
+    let data: Vec<u8> = (0..100000).map(|i| ((i * 7) % 251) as u8).collect();
+    let mut expected = BytesMut::new();
+    SnappyCompressBuilder::new().build_encoder().encode(&data, &mut expected).unwrap();
+
+    let mut encoders: Vec<_> = (0..3).map(|_| {
+      SnappyCompressBuilder::new().buffer_pool(pool.clone()).build_encoder()
+    }).collect();
+    assert_eq!(pool.len(), 0);
+    for encoder in encoders.iter_mut() {
+      let mut out = BytesMut::new();
+      encoder.encode(&data, &mut out).unwrap();
+      assert_eq!(out, expected);
+      // handed back after each call, so they all share one buffer.
+      assert_eq!(pool.len(), 1);
+    }
+  }
+
+  #[test]
+  fn decoder_returns_buffer_when_idle() {
+    let pool = BufferPool::new(4);
+    let data: Vec<u8> = (0..100000).map(|i| ((i * 7) % 251) as u8).collect();
+    let mut compressed = BytesMut::new();
+    SnappyCompressBuilder::new().build_encoder().encode(&data, &mut compressed).unwrap();
+
+    let mut decoder = FrameDecoder::new().buffer_pool(pool.clone());
+    decoder.push(compressed.freeze());
+    let mut out: Vec<u8> = Vec::new();
+    while let Some(event) = decoder.next_event().unwrap() {
+      if let DecodeEvent::Data(data) = event { out.extend_from_slice(&data) }
+      assert_eq!(pool.len(), 0);
+    }
+    assert_eq!(pool.len(), 1);
+    decoder.finish().unwrap();
+    assert_eq!(out, data);
+
+    let s = stream::iter_ok::<_, io::Error>(vec![ Bytes::from(&b"\xff\x06\x00\x00sNaPpY\x01\x09\x00\x00\xbb\x1f\x1c\x19hello"[..]) ]);
+    let buffers: Vec<Bytes> = SnappyUncompress::new(s).buffer_pool(pool.clone()).collect().wait().unwrap();
+    assert_eq!(buffers, vec![ Bytes::from(&b"hello"[..]) ]);
+    assert_eq!(pool.len(), 1);
+  }
+}
@@ -0,0 +1,85 @@
+extern crate bytes;
+extern crate futures;
+extern crate gingersnap;
+extern crate snap;
+
+#[cfg(test)]
+mod test_raw {
+  use bytes::{Bytes};
+  use futures::{Future, Stream, stream};
+  use gingersnap::{BlockDelimiter, ByteStream, SnappyRawCompress, SnappyRawUncompress};
+  use snap;
+  use std::io;
+
+  #[test]
+  fn compress_u32() {
+    let s = stream::once(Ok(Bytes::from(&b"hello"[..])));
+    let sc = SnappyRawCompress::new(s, BlockDelimiter::U32BigEndian);
+    assert_eq!(to_hex(sc), format!("{}{}", "00000007", "051068656c6c6f"));
+
+    let s = stream::once(Ok(Bytes::from(&b"hello"[..])));
+    let sc = SnappyRawCompress::new(s, BlockDelimiter::U32LittleEndian);
+    assert_eq!(to_hex(sc), format!("{}{}", "07000000", "051068656c6c6f"));
+  }
+
+  #[test]
+  fn compress_whole_stream() {
+    let s = stream::iter_ok::<_, io::Error>(vec![ Bytes::from(&b"hel"[..]), Bytes::from(&b"lo"[..]) ]);
+    let sc = SnappyRawCompress::new(s, BlockDelimiter::None);
+    assert_eq!(to_hex(sc), "051068656c6c6f");
+  }
+
+  #[test]
+  fn uncompress_snap_blocks() {
+    // what you'd get from calling `snap::Encoder` directly, with varints in between.
+    let mut encoder = snap::Encoder::new();
+    let mut input: Vec<u8> = Vec::new();
+    for block in [ &b"hello"[..], &b"999999999999999999999999"[..] ].iter() {
+      let compressed = encoder.compress_vec(block).unwrap();
+      input.push(compressed.len() as u8);
+      input.extend_from_slice(&compressed);
+    }
+    let chunks: Vec<Bytes> = input.chunks(3).map(Bytes::from).collect();
+    let su = SnappyRawUncompress::new(stream::iter_ok::<_, io::Error>(chunks), BlockDelimiter::Varint);
+    let blocks: Vec<Bytes> = su.collect().wait().unwrap();
+    assert_eq!(blocks, vec![ Bytes::from(&b"hello"[..]), Bytes::from(&b"999999999999999999999999"[..]) ]);
+  }
+
+  #[test]
+  fn roundtrip() {
+    let data: Vec<u8> = (0..200000).map(|i| ((i * 7) % 251) as u8).collect();
+    for &delimiter in [ BlockDelimiter::None, BlockDelimiter::U32BigEndian, BlockDelimiter::U32LittleEndian, BlockDelimiter::Varint ].iter() {
+      let chunks: Vec<Bytes> = data.chunks(30000).map(Bytes::from).collect();
+      let sc = SnappyRawCompress::new(stream::iter_ok::<_, io::Error>(chunks), delimiter);
+      let su = SnappyRawUncompress::new(sc, delimiter);
+      let blocks: Vec<Bytes> = su.collect().wait().unwrap();
+      let roundtrip: Vec<u8> = blocks.iter().flat_map(|b| b.to_vec()).collect();
+      assert_eq!(roundtrip, data);
+    }
+  }
+
+  #[test]
+  #[should_panic(expected="Truncated snappy block")]
+  fn truncated() {
+    let s = stream::once(Ok(Bytes::from(&b"\x00\x00\x00\x07\x05\x10hel"[..])));
+    let su = SnappyRawUncompress::new(s, BlockDelimiter::U32BigEndian);
+    to_hex(su);
+  }
+
+  #[test]
+  #[should_panic(expected="Invalid raw snappy block length")]
+  fn bad_varint() {
+    let s = stream::once(Ok(Bytes::from(&b"\xff\xff\xff\xff\xff\x01"[..])));
+    let su = SnappyRawUncompress::new(s, BlockDelimiter::Varint);
+    to_hex(su);
+  }
+
+
+  fn to_hex<S: ByteStream>(s: S) -> String {
+    let buffers: Vec<Bytes> = s.collect().wait().unwrap();
+    let strings: Vec<String> = buffers.iter().map(|buffer| {
+      buffer.as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+    }).collect();
+    strings.join("")
+  }
+}
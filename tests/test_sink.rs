@@ -0,0 +1,95 @@
+extern crate bytes;
+extern crate futures;
+extern crate gingersnap;
+
+#[cfg(test)]
+mod test_sink {
+  use bytes::{Bytes};
+  use futures::{Future, Sink, stream};
+  use futures::sink::SinkMapErr;
+  use gingersnap::{SnappyCompressBuilder, SnappyCompressSink, SnappyUncompressSink};
+  use std::io;
+
+  static HEADER: &str = "ff060000734e61507059";
+
+  #[test]
+  fn compress() {
+    let sink = vec_sink();
+    let sc = SnappyCompressSink::new(sink);
+    let (sc, _) = sc.send_all(stream::iter_ok::<_, io::Error>(vec![ Bytes::from(&b"hello"[..]) ])).wait().unwrap();
+    let frame = format!("{}{}{}", "01090000", "bb1f1c19", "68656c6c6f");
+    assert_eq!(hex(sc.into_inner().get_ref()), format!("{}{}", HEADER, frame));
+  }
+
+  #[test]
+  fn compress_empty() {
+    let sink = vec_sink();
+    let (sc, _) = SnappyCompressSink::new(sink).send_all(stream::iter_ok::<_, io::Error>(vec![])).wait().unwrap();
+    assert_eq!(hex(sc.into_inner().get_ref()), HEADER);
+  }
+
+  #[test]
+  fn coalesce_flushes_on_poll_complete() {
+    let sink = vec_sink();
+    let sc = SnappyCompressBuilder::new().coalesce(true).build_sink(sink);
+    // each `send` ends with a poll_complete, which pushes out a frame.
+    let sc = sc.send(Bytes::from(&b"hello"[..])).wait().unwrap();
+    assert_eq!(sc.get_ref().get_ref().len(), 2);
+    let sc = sc.send(Bytes::from(&b"hello"[..])).wait().unwrap();
+    let frame = format!("{}{}{}", "01090000", "bb1f1c19", "68656c6c6f");
+    assert_eq!(hex(sc.into_inner().get_ref()), format!("{}{}{}", HEADER, frame, frame));
+  }
+
+  #[test]
+  fn uncompress() {
+    let sink = vec_sink();
+    let su = SnappyUncompressSink::new(sink);
+    let input = from_hexes(vec![ &HEADER[0..2], &HEADER[2..6], &HEADER[6..], "000a00", "005977", "2563180039", "5a0100" ]);
+    let (su, _) = su.send_all(stream::iter_ok::<_, io::Error>(input)).wait().unwrap();
+    assert_eq!(hex(su.into_inner().get_ref()), "393939393939393939393939393939393939393939393939");
+  }
+
+  #[test]
+  #[should_panic(expected="Truncated snappy frame")]
+  fn uncompress_truncated() {
+    let sink = vec_sink();
+    let su = SnappyUncompressSink::new(sink);
+    let input = from_hexes(vec![ HEADER, "000a0000", "59772563", "1800395" ]);
+    let _ = su.send_all(stream::iter_ok::<_, io::Error>(input)).wait().unwrap();
+  }
+
+  #[test]
+  fn roundtrip() {
+    let data: Vec<u8> = (0..200000).map(|i| ((i * 7) % 251) as u8).collect();
+    let inner = vec_sink();
+    let su = SnappyUncompressSink::new(inner);
+    let sc = SnappyCompressSink::new(su);
+    let chunks: Vec<Bytes> = data.chunks(10000).map(Bytes::from).collect();
+    let (sc, _) = sc.send_all(stream::iter_ok::<_, io::Error>(chunks)).wait().unwrap();
+    let buffers = sc.into_inner().into_inner().into_inner();
+    let roundtrip: Vec<u8> = buffers.iter().flat_map(|b| b.to_vec()).collect();
+    assert_eq!(roundtrip, data);
+  }
+
+
+  fn vec_sink() -> SinkMapErr<Vec<Bytes>, fn(()) -> io::Error> {
+    fn oops(_: ()) -> io::Error { io::Error::other("oops") }
+    Vec::new().sink_map_err(oops)
+  }
+
+  fn hex(buffers: &[Bytes]) -> String {
+    let strings: Vec<String> = buffers.iter().map(|buffer| {
+      buffer.as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+    }).collect();
+    strings.join("")
+  }
+
+  fn from_hexes(vec: Vec<&str>) -> Vec<Bytes> {
+    vec.iter().map(|s| {
+      let bytes: Vec<u8> = (0 .. s.len() / 2).map(|i| {
+        u8::from_str_radix(&s[i * 2 .. (i + 1) * 2], 16).unwrap()
+      }).collect();
+      Bytes::from(bytes)
+    }).collect()
+  }
+}
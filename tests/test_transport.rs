@@ -0,0 +1,74 @@
+extern crate futures;
+extern crate gingersnap;
+extern crate tokio;
+
+#[cfg(test)]
+mod test_transport {
+  use futures::{Future, Stream};
+  use gingersnap::{SnappyReader, SnappyTransport, SnappyWriter};
+  use std::net::{Shutdown, SocketAddr};
+  use tokio::io;
+  use tokio::net::{TcpListener, TcpStream};
+  use tokio::runtime::Runtime;
+
+  #[test]
+  fn loopback_echo() {
+    let addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+    let listener = TcpListener::bind(&addr).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let message: Vec<u8> = (0..200000).map(|i| ((i * 7) % 251) as u8).collect();
+
+    // the server reads one framed request until the client shuts down its
+    // side, then echoes it back in upper case.
+    let server = listener.incoming().take(1).for_each(|socket| {
+      let transport = SnappyTransport::wrap(socket);
+      io::read_to_end(transport, Vec::new()).and_then(|(transport, request)| {
+        let response: Vec<u8> = request.iter().map(|b| b.wrapping_add(1)).collect();
+        io::write_all(transport, response)
+      }).and_then(|(transport, _)| {
+        io::shutdown(transport)
+      }).map(|_| ())
+    }).map_err(|e| panic!("server: {}", e));
+
+    let request = message.clone();
+    let client = TcpStream::connect(&addr).and_then(move |socket| {
+      io::write_all(SnappyTransport::wrap(socket), request)
+    }).and_then(|(transport, _)| {
+      io::shutdown(transport)
+    }).and_then(|transport| {
+      // tokio's shutdown doesn't close the socket's write side, so do it by hand.
+      transport.transport_ref().shutdown(Shutdown::Write)?;
+      Ok(transport)
+    }).and_then(|transport| {
+      io::read_to_end(transport, Vec::new())
+    });
+
+    let mut runtime = Runtime::new().unwrap();
+    runtime.spawn(server);
+    let (_, response) = runtime.block_on(client).unwrap();
+    let expected: Vec<u8> = message.iter().map(|b| b.wrapping_add(1)).collect();
+    assert_eq!(response, expected);
+  }
+
+  #[test]
+  fn poll_read_one_direction() {
+    let addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+    let listener = TcpListener::bind(&addr).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = listener.incoming().take(1).for_each(|socket| {
+      io::write_all(SnappyWriter::new(socket), b"hello".to_vec()).and_then(|(writer, _)| {
+        io::shutdown(writer)
+      }).map(|_| ())
+    }).map_err(|e| panic!("server: {}", e));
+
+    let client = TcpStream::connect(&addr).and_then(|socket| {
+      io::read_exact(SnappyReader::new(socket), [0u8; 5])
+    });
+
+    let mut runtime = Runtime::new().unwrap();
+    runtime.spawn(server);
+    let (_, buffer) = runtime.block_on(client).unwrap();
+    assert_eq!(&buffer, b"hello");
+  }
+}
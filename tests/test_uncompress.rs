@@ -106,6 +106,44 @@ mod test_uncompress {
     to_hex(sc);
   }
 
+  #[test]
+  fn output_limit() {
+    let s = from_hex(format!("{}{}{}{}", HEADER, "000a0000", "59772563", "1800395a0100"));
+    let sc = SnappyUncompress::new(s).max_output(24).max_ratio(1.0).max_buffered(14);
+    assert_eq!(to_hex(sc), "393939393939393939393939393939393939393939393939");
+  }
+
+  #[test]
+  #[should_panic(expected="exceeds limit of 20 bytes")]
+  fn output_limit_exceeded() {
+    let s = from_hex(format!("{}{}{}{}", HEADER, "000a0000", "59772563", "1800395a0100"));
+    let sc = SnappyUncompress::new(s).max_output(20);
+    to_hex(sc);
+  }
+
+  #[test]
+  #[should_panic(expected="exceeds expansion ratio of 0.5")]
+  fn ratio_limit_exceeded() {
+    let s = from_hex(format!("{}{}{}{}", HEADER, "000a0000", "59772563", "1800395a0100"));
+    let sc = SnappyUncompress::new(s).max_ratio(0.5);
+    to_hex(sc);
+  }
+
+  #[test]
+  #[should_panic(expected="exceeds buffer limit of 10 bytes")]
+  fn buffer_limit_exceeded() {
+    let s = from_hexes(vec![ HEADER, "01090000" ]);
+    let sc = SnappyUncompress::new(s).max_buffered(10);
+    to_hex(sc);
+  }
+
+  #[test]
+  fn recover() {
+    let s = from_hexes(vec![ HEADER, "01090000ff1f1c1968656c6c6f", "000a0000", "59772563", "1800395a0100" ]);
+    let sc = SnappyUncompress::new(s).recover(true);
+    assert_eq!(to_hex(sc), "393939393939393939393939393939393939393939393939");
+  }
+
 
   fn to_hex<S: ByteStream>(s: S) -> String {
     let buffers: Vec<Bytes> = s.collect().wait().unwrap();
@@ -0,0 +1,145 @@
+extern crate bytes;
+extern crate futures;
+extern crate gingersnap;
+
+#[cfg(test)]
+mod test_xerial {
+  use bytes::{Bytes};
+  use futures::{Async, Future, Stream, stream};
+  use gingersnap::{ByteStream, XerialSnappyCompress, XerialSnappyUncompress};
+  use std::io;
+  use std::vec;
+
+  static HEADER: &str = "82534e41505059000000000100000001";
+
+  #[test]
+  fn compress() {
+    let s = stream::once(Ok(Bytes::from(&b"hello"[..])));
+    let sc = XerialSnappyCompress::new(s);
+    assert_eq!(to_hex(sc), format!("{}{}{}", HEADER, "00000007", "051068656c6c6f"));
+  }
+
+  #[test]
+  fn compress_never_polls_finished_stream() {
+    let mut sc = XerialSnappyCompress::new(PanicAfterEnd::new(b"hello"));
+    let mut buffers = Vec::new();
+    while let Async::Ready(Some(b)) = sc.poll().unwrap() { buffers.push(b) }
+    assert_eq!(buffers.len(), 2);
+    assert_eq!(sc.poll().unwrap(), Async::Ready(None));
+  }
+
+  #[test]
+  fn uncompress() {
+    let s = from_hexes(vec![ &HEADER[0..6], &HEADER[6..], "000000", "07", "051068", "656c6c6f" ]);
+    let su = XerialSnappyUncompress::new(s);
+    assert_eq!(to_hex(su), "68656c6c6f");
+  }
+
+  #[test]
+  fn uncompress_reference() {
+    // from kafka-python's tests, which check it against snappy-java: two
+    // 300-byte blocks, compressed by the C++ snappy library.
+    let block = "ac0214{}fe0600fe0600fe0600fe0600960600";
+    let s = from_hexes(vec![
+      HEADER,
+      "00000018", &block.replace("{}", "534e41505059"),
+      "00000018", &block.replace("{}", "58455249414c"),
+    ]);
+    let su = XerialSnappyUncompress::new(s);
+    let buffers: Vec<Bytes> = su.collect().wait().unwrap();
+    let data: Vec<u8> = buffers.iter().flat_map(|b| b.to_vec()).collect();
+    assert_eq!(data, [ b"SNAPPY".repeat(50), b"XERIAL".repeat(50) ].concat());
+  }
+
+  #[test]
+  fn uncompress_concatenated() {
+    let s = from_hexes(vec![ HEADER, "00000007051068656c6c6f", HEADER, "00000007051068656c6c6f" ]);
+    let su = XerialSnappyUncompress::new(s);
+    assert_eq!(to_hex(su), "68656c6c6f68656c6c6f");
+  }
+
+  #[test]
+  fn roundtrip() {
+    let data: Vec<u8> = (0..200000).map(|i| ((i * 7) % 251) as u8).collect();
+    let s = stream::iter_ok::<_, io::Error>(data.chunks(50000).map(Bytes::from).collect::<Vec<Bytes>>());
+    let su = XerialSnappyUncompress::new(XerialSnappyCompress::new(s));
+    let buffers: Vec<Bytes> = su.collect().wait().unwrap();
+    let roundtrip: Vec<u8> = buffers.iter().flat_map(|b| b.to_vec()).collect();
+    assert_eq!(roundtrip, data);
+  }
+
+  #[test]
+  #[should_panic(expected="missing magic")]
+  fn missing_magic() {
+    let s = from_hexes(vec![ "00000007051068656c6c6f" ]);
+    let su = XerialSnappyUncompress::new(s);
+    to_hex(su);
+  }
+
+  #[test]
+  #[should_panic(expected="mangled magic")]
+  fn mangled_magic() {
+    let s = from_hexes(vec![ "82534e41505059ff0000000100000001", "00000007051068656c6c6f" ]);
+    let su = XerialSnappyUncompress::new(s);
+    to_hex(su);
+  }
+
+  #[test]
+  #[should_panic(expected="Unsupported xerial snappy version")]
+  fn future_version() {
+    let s = from_hexes(vec![ "82534e41505059000000000200000002", "00000007051068656c6c6f" ]);
+    let su = XerialSnappyUncompress::new(s);
+    to_hex(su);
+  }
+
+  #[test]
+  #[should_panic(expected="Truncated xerial snappy block")]
+  fn truncated() {
+    let s = from_hexes(vec![ HEADER, "000000070510686c" ]);
+    let su = XerialSnappyUncompress::new(s);
+    to_hex(su);
+  }
+
+
+  // yields one buffer, then ends, then panics if it's polled again.
+  pub struct PanicAfterEnd {
+    data: Option<Bytes>,
+    done: bool,
+  }
+
+  impl PanicAfterEnd {
+    pub fn new(data: &[u8]) -> PanicAfterEnd {
+      PanicAfterEnd { data: Some(Bytes::from(data)), done: false }
+    }
+  }
+
+  impl Stream for PanicAfterEnd {
+    type Item = Bytes;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Result<Async<Option<Bytes>>, io::Error> {
+      assert!(!self.done, "polled after completion");
+      let data = self.data.take();
+      self.done = data.is_none();
+      Ok(Async::Ready(data))
+    }
+  }
+
+  fn to_hex<S: ByteStream>(s: S) -> String {
+    let buffers: Vec<Bytes> = s.collect().wait().unwrap();
+    let strings: Vec<String> = buffers.iter().map(|buffer| {
+      buffer.as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+    }).collect();
+    strings.join("")
+  }
+
+  fn from_hexes(vec: Vec<&str>) -> stream::IterOk<vec::IntoIter<Bytes>, io::Error> {
+    let bytes_vec: Vec<Bytes> = vec.iter().map(|s| {
+      let bytes: Vec<u8> = (0 .. s.len() / 2).map(|i| {
+        u8::from_str_radix(&s[i * 2 .. (i + 1) * 2], 16).unwrap()
+      }).collect();
+      Bytes::from(bytes)
+    }).collect();
+    stream::iter_ok(bytes_vec)
+  }
+}